@@ -0,0 +1,41 @@
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turns coloring off for the rest of the run, e.g. for `--no-color`.
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// Colors are used only when stderr is a terminal and neither `--no-color`
+/// nor a non-empty `NO_COLOR` environment variable asked us not to.
+fn enabled() -> bool {
+    if DISABLED.load(Ordering::Relaxed) {
+        return false;
+    }
+    if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        return false;
+    }
+    io::stderr().is_terminal()
+}
+
+fn paint(code: &str, text: &str) -> String {
+    if enabled() {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn red(text: &str) -> String {
+    paint("31", text)
+}
+
+pub fn yellow(text: &str) -> String {
+    paint("33", text)
+}
+
+pub fn dim(text: &str) -> String {
+    paint("2", text)
+}
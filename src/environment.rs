@@ -10,6 +10,12 @@ pub struct Environment {
     values: HashMap<String, Value>,
 }
 
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Environment {
     pub fn new() -> Self {
         Environment {
@@ -29,33 +35,85 @@ impl Environment {
         self.values.insert(name, value);
     }
 
-    pub fn assign(&mut self, name: Token, value: Value) -> Result<(), RuntimeError> {
-        if self.values.contains_key(&name.lexeme) {
-            self.values.insert(name.lexeme, value);
+    pub fn assign(&mut self, name: &Token, value: Value) -> Result<(), RuntimeError> {
+        if let Some(slot) = self.values.get_mut(&name.lexeme) {
+            *slot = value;
             return Ok(());
         }
 
         match &self.enclosing {
             Some(enclose) => enclose.borrow_mut().assign(name, value),
-            None => Err(RuntimeError::Error {
-                message: format!("Undefined variable '{}'.", name.lexeme),
-                line: name.line,
-                token: name,
-            }),
+            None => Err(undefined(name)),
+        }
+    }
+
+    /// The environment `distance` enclosing links out from `env`, as
+    /// counted by the resolver. A chain shorter than that means the
+    /// resolver and the runtime disagree, which is reported against `name`
+    /// rather than trusted.
+    pub fn ancestor(
+        env: &Rc<RefCell<Environment>>,
+        distance: usize,
+        name: &Token,
+    ) -> Result<Rc<RefCell<Environment>>, RuntimeError> {
+        let mut environment = Rc::clone(env);
+        for _ in 0..distance {
+            let enclosing = environment.borrow().enclosing.clone();
+            environment = enclosing.ok_or_else(|| undefined(name))?;
         }
+        Ok(environment)
+    }
+
+    pub fn get_at(
+        env: &Rc<RefCell<Environment>>,
+        distance: usize,
+        name: &Token,
+    ) -> Result<Value, RuntimeError> {
+        Environment::ancestor(env, distance, name)?
+            .borrow()
+            .values
+            .get(&name.lexeme)
+            .cloned()
+            .ok_or_else(|| undefined(name))
     }
 
-    pub fn get(&self, name: Token) -> Result<Value, RuntimeError> {
+    pub fn assign_at(
+        env: &Rc<RefCell<Environment>>,
+        distance: usize,
+        name: &Token,
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        let environment = Environment::ancestor(env, distance, name)?;
+        let mut environment = environment.borrow_mut();
+        let slot = environment
+            .values
+            .get_mut(&name.lexeme)
+            .ok_or_else(|| undefined(name))?;
+        *slot = value;
+        Ok(())
+    }
+
+    /// The value defined under `name` in this scope itself, without
+    /// looking at enclosing scopes.
+    pub fn lookup(&self, name: &str) -> Option<Value> {
+        self.values.get(name).cloned()
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Value, RuntimeError> {
         match self.values.get(&name.lexeme) {
             Some(val) => Ok(val.clone()),
             None => match &self.enclosing {
                 Some(enclose) => enclose.borrow().get(name),
-                None => Err(RuntimeError::Error {
-                    message: format!("Undefined variable '{}'.", name.lexeme),
-                    line: name.line,
-                    token: name,
-                }),
+                None => Err(undefined(name)),
             },
         }
     }
 }
+
+fn undefined(name: &Token) -> RuntimeError {
+    RuntimeError::Error {
+        message: format!("Undefined variable '{}'.", name.lexeme),
+        line: name.line,
+        token: name.clone(),
+    }
+}
@@ -0,0 +1,259 @@
+use crate::color;
+use crate::evaluate::Frame;
+use crate::lexer::{Token, TokenType};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+// Every diagnostic starts with `[line N]` followed by its kind, so tools
+// only have to understand one shape:
+//
+//   [line N] Error: <message>                  (lexer)
+//   [line N] Error at '<lexeme>': <message>    (parser, resolver)
+//   [line N] Error at end: <message>           (parser, at end of input)
+//   [line N] Runtime Error: <message>          (interpreter)
+//   Error: <message>                           (a source file that can't be read)
+//   in <fn> -> <fn> -> <fn> (N frames)         (after a runtime error in a call)
+//     at <fn> (line N)                         (the same, with --backtrace)
+//
+// Warnings never stop a run and use their own shape:
+//
+//   Warning [line N]: <message>                (resolver lints)
+//
+// With --json-errors each error or warning is instead one JSON object per
+// line, and `exit` ends the output with a count of each:
+//
+//   {"severity":"error","line":N,"column":N,"message":"<message>"}
+//   {"summary":{"errors":N,"warnings":N}}
+//
+// Columns are 1-based and count chars. The column is null when it isn't
+// known, e.g. for an error at a token the parser made up, and both the line
+// and the column are null for an error about the whole file. Backtraces are
+// left out.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// One finding from `resolver::analyze`, for tools that want diagnostics
+/// as data rather than as text on stderr. `code` names the stage that
+/// found it: "lex", "parse", "resolve", or the name of the lint for a
+/// warning. `column` is 1-based, or 0 when it isn't known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    pub code: &'static str,
+}
+
+// Exit codes. Static errors and runtime errors use the BSD sysexits values
+// that reference Lox uses; anything else is a plain failure.
+pub const EXIT_SUCCESS: i32 = 0;
+/// Bad command line: missing arguments or an unknown command.
+pub const EXIT_FAILURE: i32 = 1;
+/// A lexer, parser or resolver error, or a source file that isn't UTF-8.
+pub const EX_DATAERR: i32 = 65;
+/// The script file couldn't be read.
+pub const EX_NOINPUT: i32 = 66;
+/// A runtime error, including a panic in the interpreter thread.
+pub const EX_SOFTWARE: i32 = 70;
+
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+static ERROR_COUNT: AtomicUsize = AtomicUsize::new(0);
+static WARNING_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Reports errors and warnings as JSON lines, e.g. for `--json-errors`.
+pub fn enable_json_errors() {
+    JSON_ERRORS.store(true, Ordering::Relaxed);
+}
+
+/// Exits with `code`, first printing the JSON summary line if JSON errors
+/// are on. Every exit after a diagnostic could have been reported goes
+/// through here.
+pub fn exit(code: i32) -> ! {
+    if JSON_ERRORS.load(Ordering::Relaxed) {
+        eprintln!(
+            "{{\"summary\":{{\"errors\":{},\"warnings\":{}}}}}",
+            ERROR_COUNT.load(Ordering::Relaxed),
+            WARNING_COUNT.load(Ordering::Relaxed)
+        );
+    }
+    std::process::exit(code)
+}
+
+// Prints the diagnostic as JSON if JSON errors are on; returns whether it
+// did.
+fn report_json(severity: Severity, line: usize, column: usize, message: &str) -> bool {
+    if !JSON_ERRORS.load(Ordering::Relaxed) {
+        return false;
+    }
+    let severity = match severity {
+        Severity::Error => {
+            ERROR_COUNT.fetch_add(1, Ordering::Relaxed);
+            "error"
+        }
+        Severity::Warning => {
+            WARNING_COUNT.fetch_add(1, Ordering::Relaxed);
+            "warning"
+        }
+    };
+    eprintln!(
+        "{{\"severity\":\"{}\",\"line\":{},\"column\":{},\"message\":{}}}",
+        severity,
+        json_position(line),
+        json_position(column),
+        json_string(message)
+    );
+    true
+}
+
+// Lines and columns start at 1, so 0 means unknown.
+fn json_position(position: usize) -> String {
+    match position {
+        0 => "null".to_string(),
+        position => position.to_string(),
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn line_prefix(line: usize) -> String {
+    color::dim(&format!("[line {}]", line))
+}
+
+/// Reports a problem with a source file as a whole, before any of it could
+/// be scanned.
+pub fn report_file(message: &str) {
+    if report_json(Severity::Error, 0, 0, message) {
+        return;
+    }
+    eprintln!("{}: {}", color::red("Error"), message);
+}
+
+pub fn report_lex(line: usize, column: usize, message: &str) {
+    if report_json(Severity::Error, line, column, message) {
+        return;
+    }
+    eprintln!("{} {}: {}", line_prefix(line), color::red("Error"), message);
+}
+
+pub fn report_parse(token: &Token, message: &str) {
+    if report_json(Severity::Error, token.line, token.column, message) {
+        return;
+    }
+    let location = if token.token_type == TokenType::EOF {
+        " at end".to_string()
+    } else {
+        format!(" at '{}'", token.lexeme)
+    };
+    eprintln!(
+        "{} {}{}: {}",
+        line_prefix(token.line),
+        color::red("Error"),
+        location,
+        message
+    );
+}
+
+pub fn report_runtime(line: usize, column: usize, message: &str) {
+    if report_json(Severity::Error, line, column, message) {
+        return;
+    }
+    eprintln!(
+        "{} {}: {}",
+        line_prefix(line),
+        color::red("Runtime Error"),
+        message
+    );
+}
+
+pub fn report_warning(line: usize, column: usize, message: &str) {
+    if report_json(Severity::Warning, line, column, message) {
+        return;
+    }
+    eprintln!(
+        "{} {}: {}",
+        color::yellow("Warning"),
+        color::dim(&format!("[line {}]", line)),
+        message
+    );
+}
+
+static FULL_BACKTRACE: AtomicBool = AtomicBool::new(false);
+
+/// Prints every frame after a runtime error, e.g. for `--backtrace`.
+pub fn enable_backtrace() {
+    FULL_BACKTRACE.store(true, Ordering::Relaxed);
+}
+
+// Only the innermost frames are named in the short form; deep recursion
+// would otherwise print the same name a thousand times.
+const BACKTRACE_FRAMES: usize = 3;
+
+pub fn report_backtrace(line: usize, call_stack: &[Frame]) {
+    if call_stack.is_empty() || JSON_ERRORS.load(Ordering::Relaxed) {
+        return;
+    }
+    if FULL_BACKTRACE.load(Ordering::Relaxed) {
+        report_full_backtrace(line, call_stack);
+        return;
+    }
+    let shown: Vec<&str> = call_stack[call_stack.len().saturating_sub(BACKTRACE_FRAMES)..]
+        .iter()
+        .map(|frame| frame.name.as_str())
+        .collect();
+    let frames = if call_stack.len() == 1 {
+        "frame"
+    } else {
+        "frames"
+    };
+    eprintln!(
+        "in {} ({} {})",
+        shown.join(" -> "),
+        call_stack.len(),
+        frames
+    );
+}
+
+// Innermost frame first. Each frame is shown at the line it was executing,
+// which is the error line for the innermost one and the call site of the
+// frame above it for the rest. Runs of identical frames are folded.
+fn report_full_backtrace(line: usize, call_stack: &[Frame]) {
+    let mut frames = Vec::new();
+    let mut current_line = line;
+    for frame in call_stack.iter().rev() {
+        frames.push(format!("  at {} (line {})", frame.name, current_line));
+        current_line = frame.call_line;
+    }
+    frames.push(format!("  at <script> (line {})", current_line));
+
+    let mut index = 0;
+    while index < frames.len() {
+        let repeated = frames[index..]
+            .iter()
+            .take_while(|frame| **frame == frames[index])
+            .count();
+        eprintln!("{}", frames[index]);
+        if repeated > 1 {
+            eprintln!("  ... repeated {} more times", repeated - 1);
+        }
+        index += repeated;
+    }
+}
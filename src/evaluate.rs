@@ -1,13 +1,24 @@
 use crate::environment::Environment;
-use crate::function::{Clock, LoxCallable, LoxFunction};
-use crate::lexer::{return_tokens, Literal, Token, TokenType};
+use crate::errors;
+use crate::function::{
+    ApproxEq, AssertEq, Benchmark, Bin, CharAt, Chars, Chr, Clamp, Clock, Contains, DeepCopy,
+    Format, GetLog, Hex, IndexOf, Insert, Join, Lower, LoxCallable, LoxFunction, MathFunction, Max,
+    Min, Ord, Pop, Push, Random, RandomInt, ReadFile, Remove, Reverse, Seed, SideEffect, Sort,
+    Split, Substring, ToFixed, Trim, Upper, WriteFile, Xorshift,
+};
+use crate::lexer::{read_source, scan_tokens, Literal, SourceError, Token, TokenType};
 use crate::parse::{Expr, Parser, Stmt};
+use crate::resolver::Resolver;
 use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
-use std::fs;
-use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
+// Lox calls recurse on the Rust stack, so runaway recursion is cut off here
+// rather than crashing the interpreter.
+const MAX_CALL_DEPTH: usize = 1000;
+
 #[derive(Clone)]
 pub enum Value {
     Number(f64),
@@ -15,20 +26,72 @@ pub enum Value {
     Boolean(bool),
     Nil,
     Function(Rc<dyn LoxCallable>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Module(Rc<Module>),
+}
+
+/// The exported declarations of a file brought in with `import ... as`.
+pub struct Module {
+    pub name: String,
+    pub members: HashMap<String, Value>,
 }
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            // Match reference Lox rather than Rust's `inf`/`NaN`.
+            Value::Number(value) if value.is_nan() => write!(f, "nan"),
+            Value::Number(value) if value.is_infinite() => {
+                write!(
+                    f,
+                    "{}",
+                    if *value > 0.0 {
+                        "Infinity"
+                    } else {
+                        "-Infinity"
+                    }
+                )
+            }
+            // Negative zero prints as plain `0`; it already compares equal.
+            Value::Number(value) if *value == 0.0 => write!(f, "0"),
             Value::Number(value) => write!(f, "{}", value),
             Value::String(value) => write!(f, "{}", value),
             Value::Boolean(value) => write!(f, "{:?}", value),
             Value::Nil => write!(f, "nil"),
             Value::Function(value) => write!(f, "{}", value.to_string()),
+            Value::List(values) => write_list(f, values, &mut Vec::new()),
+            Value::Module(module) => write!(f, "<module {}>", module.name),
         }
     }
 }
 
+// Strings are quoted inside containers so `["a, b"]` and `["a", "b"]` print
+// differently; a bare top-level string is still printed as-is. A list that
+// contains itself prints the inner occurrence as `[...]`.
+fn write_list(
+    f: &mut fmt::Formatter<'_>,
+    values: &Rc<RefCell<Vec<Value>>>,
+    seen: &mut Vec<*const RefCell<Vec<Value>>>,
+) -> fmt::Result {
+    if seen.contains(&Rc::as_ptr(values)) {
+        return write!(f, "[...]");
+    }
+    seen.push(Rc::as_ptr(values));
+    write!(f, "[")?;
+    for (i, value) in values.borrow().iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        match value {
+            Value::String(value) => write!(f, "\"{}\"", value)?,
+            Value::List(inner) => write_list(f, inner, seen)?,
+            value => write!(f, "{}", value)?,
+        }
+    }
+    seen.pop();
+    write!(f, "]")
+}
+
 pub struct Return {
     pub value: Value,
 }
@@ -47,11 +110,54 @@ pub enum RuntimeError {
         token: Token,
     },
     Return(Return),
+    Break,
+    Continue,
+}
+
+/// A function call that is still running: who was called, and from which
+/// line.
+pub struct Frame {
+    pub name: String,
+    pub call_line: usize,
+}
+
+/// The global definitions and import state at some point, taken with
+/// `snapshot_globals` and put back with `restore_globals`. Values are
+/// shared, not deep-copied, so a list mutated after the snapshot stays
+/// mutated.
+#[derive(Clone)]
+pub struct GlobalsSnapshot {
+    globals: Environment,
+    imported: HashSet<PathBuf>,
+    modules: HashMap<PathBuf, Rc<Module>>,
 }
 
 pub struct Evaluate {
     pub globals: Rc<RefCell<Environment>>,
     environment: Rc<RefCell<Environment>>,
+    locals: HashMap<usize, usize>,
+    call_stack: Vec<Frame>,
+    // Files currently being run, innermost import last.
+    files: Vec<PathBuf>,
+    imported: HashSet<PathBuf>,
+    modules: HashMap<PathBuf, Rc<Module>>,
+    // Set by `run --trace`: log each statement to stderr before it runs.
+    pub trace: bool,
+    // Set by `run --lenient`: reading an undefined variable warns and gives
+    // nil instead of failing. Assigning to one is still an error.
+    pub lenient: bool,
+    // Set by `run --sandbox`; see `run::RunOptions`. Must be set before
+    // `define_globals`.
+    pub sandbox: bool,
+    // Set by `run --max-iterations=N`: a single loop that runs its body
+    // more than N times stops with a runtime error. Unlimited when None.
+    pub max_iterations: Option<usize>,
+}
+
+impl Default for Evaluate {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Evaluate {
@@ -60,78 +166,221 @@ impl Evaluate {
         Evaluate {
             environment: Rc::clone(&globals),
             globals,
+            locals: HashMap::new(),
+            call_stack: Vec::new(),
+            files: Vec::new(),
+            imported: HashSet::new(),
+            modules: HashMap::new(),
+            trace: false,
+            lenient: false,
+            sandbox: false,
+            max_iterations: None,
         }
     }
 
     pub fn define_globals(&mut self) {
-        self.globals
-            .borrow_mut()
-            .define(String::from("clock"), Value::Function(Rc::new(Clock)));
+        let mut globals = self.globals.borrow_mut();
+        if !self.sandbox {
+            globals.define(String::from("clock"), Value::Function(Rc::new(Clock)));
+            globals.define(
+                String::from("benchmark"),
+                Value::Function(Rc::new(Benchmark)),
+            );
+            globals.define(String::from("readFile"), Value::Function(Rc::new(ReadFile)));
+            globals.define(
+                String::from("writeFile"),
+                Value::Function(Rc::new(WriteFile)),
+            );
+        }
+        globals.define(String::from("hex"), Value::Function(Rc::new(Hex)));
+        globals.define(String::from("bin"), Value::Function(Rc::new(Bin)));
+        globals.define(
+            String::from("substring"),
+            Value::Function(Rc::new(Substring)),
+        );
+        globals.define(String::from("indexOf"), Value::Function(Rc::new(IndexOf)));
+        globals.define(String::from("contains"), Value::Function(Rc::new(Contains)));
+        globals.define(String::from("split"), Value::Function(Rc::new(Split)));
+        globals.define(String::from("join"), Value::Function(Rc::new(Join)));
+        globals.define(String::from("upper"), Value::Function(Rc::new(Upper)));
+        globals.define(String::from("lower"), Value::Function(Rc::new(Lower)));
+        globals.define(String::from("trim"), Value::Function(Rc::new(Trim)));
+        let rng = Rc::new(if self.sandbox {
+            Xorshift::from_seed(0)
+        } else {
+            Xorshift::from_time()
+        });
+        globals.define(
+            String::from("random"),
+            Value::Function(Rc::new(Random::new(Rc::clone(&rng)))),
+        );
+        globals.define(
+            String::from("randomInt"),
+            Value::Function(Rc::new(RandomInt::new(Rc::clone(&rng)))),
+        );
+        globals.define(
+            String::from("seed"),
+            Value::Function(Rc::new(Seed::new(rng))),
+        );
+        globals.define(String::from("min"), Value::Function(Rc::new(Min)));
+        globals.define(String::from("max"), Value::Function(Rc::new(Max)));
+        globals.define(String::from("clamp"), Value::Function(Rc::new(Clamp)));
+        globals.define(
+            String::from("sin"),
+            Value::Function(Rc::new(MathFunction::new(f64::sin))),
+        );
+        globals.define(
+            String::from("cos"),
+            Value::Function(Rc::new(MathFunction::new(f64::cos))),
+        );
+        globals.define(
+            String::from("tan"),
+            Value::Function(Rc::new(MathFunction::new(f64::tan))),
+        );
+        globals.define(
+            String::from("log"),
+            Value::Function(Rc::new(MathFunction::new(f64::ln))),
+        );
+        globals.define(
+            String::from("log10"),
+            Value::Function(Rc::new(MathFunction::new(f64::log10))),
+        );
+        globals.define(
+            String::from("exp"),
+            Value::Function(Rc::new(MathFunction::new(f64::exp))),
+        );
+        globals.define(String::from("chars"), Value::Function(Rc::new(Chars)));
+        globals.define(String::from("charAt"), Value::Function(Rc::new(CharAt)));
+        globals.define(String::from("assertEq"), Value::Function(Rc::new(AssertEq)));
+        globals.define(String::from("format"), Value::Function(Rc::new(Format)));
+        globals.define(String::from("push"), Value::Function(Rc::new(Push)));
+        globals.define(String::from("pop"), Value::Function(Rc::new(Pop)));
+        globals.define(String::from("insert"), Value::Function(Rc::new(Insert)));
+        globals.define(String::from("remove"), Value::Function(Rc::new(Remove)));
+        globals.define(String::from("approxEq"), Value::Function(Rc::new(ApproxEq)));
+        globals.define(String::from("toFixed"), Value::Function(Rc::new(ToFixed)));
+        globals.define(String::from("ord"), Value::Function(Rc::new(Ord)));
+        globals.define(String::from("chr"), Value::Function(Rc::new(Chr)));
+        globals.define(String::from("reverse"), Value::Function(Rc::new(Reverse)));
+        globals.define(String::from("sort"), Value::Function(Rc::new(Sort)));
+        globals.define(String::from("copy"), Value::Function(Rc::new(DeepCopy)));
+        let log = Rc::new(RefCell::new(Vec::new()));
+        globals.define(
+            String::from("sideEffect"),
+            Value::Function(Rc::new(SideEffect::new(Rc::clone(&log)))),
+        );
+        globals.define(
+            String::from("getLog"),
+            Value::Function(Rc::new(GetLog::new(log))),
+        );
     }
 
     pub fn execute(&mut self, stmt: Stmt, flag: bool) -> Result<(), RuntimeError> {
+        if self.trace {
+            trace_stmt(&stmt);
+        }
         match stmt {
-            Stmt::Expression(expr) => match self.visit_expression_stmt(&expr) {
-                Ok(value) => {
-                    if flag {
-                        println!("{}", value);
-                    }
+            Stmt::Expression(expr) => {
+                let value = self.visit_expression_stmt(&expr)?;
+                if flag {
+                    println!("{}", value);
                 }
-                Err(error) => match error {
-                    RuntimeError::Error {
-                        message,
-                        line,
-                        token,
-                    } => {
-                        let _ = token;
-                        writeln!(io::stderr(), "[line {}] Runtime Error: {}", line, message)
-                            .unwrap();
-                        std::process::exit(70)
-                    }
-                    _ => return Ok(()),
-                },
-            },
-            Stmt::Print(expr) => {
-                self.visit_print_stmt(&expr);
-                return Ok(());
-            }
-            Stmt::Block(statements) => return self.visit_block_stmt(statements),
-            Stmt::Var(name, expr) => {
-                self.visit_var_stmt(&expr, &name);
-                return Ok(());
+                Ok(())
             }
+            Stmt::Print(exprs) => self.visit_print_stmt(&exprs),
+            Stmt::Block(statements) => self.visit_block_stmt(statements),
+            Stmt::Var(name, expr) => self.visit_var_stmt(&expr, &name),
             Stmt::If(condition, then_branch, else_branch) => {
-                match self.visit_if_statement(condition, *then_branch, *else_branch) {
-                    Err(RuntimeError::Return(ret)) => return Err(RuntimeError::Return(ret)),
-                    _ => (),
-                }
+                self.visit_if_statement(condition, *then_branch, *else_branch)
             }
-            Stmt::While(condition, body) => return self.visit_while_stmt(&condition, &body),
-            Stmt::Function(name, parameter, body) => {
-                self.visit_function_stmt(&name, parameter, body);
-                return Ok(());
+            Stmt::While(keyword, condition, body, increment) => {
+                self.visit_while_stmt(&keyword, &condition, &body, increment.as_ref())
             }
-            Stmt::Return(_keyword, value) => {
-                match self.visit_return_stmt(&_keyword, &Some(value)) {
-                    Ok(_) => return Ok(()),
-                    Err(error) => return Err(error),
-                }
+            Stmt::DoWhile(keyword, body, condition) => {
+                self.visit_do_while_stmt(&keyword, &body, &condition)
+            }
+            Stmt::Switch(subject, cases, default) => {
+                self.visit_switch_stmt(&subject, cases, default)
+            }
+            Stmt::Break(_) => Err(RuntimeError::Break),
+            Stmt::Continue(_) => Err(RuntimeError::Continue),
+            Stmt::Export(_, declaration) => self.execute(*declaration, flag),
+            Stmt::Import(keyword, path, alias) => {
+                self.visit_import_stmt(&keyword, &path, alias.as_ref())
             }
+            Stmt::Function(name, parameter, body, return_type) => {
+                self.visit_function_stmt(&name, parameter, body, return_type)
+            }
+            Stmt::Return(keyword, value) => self.visit_return_stmt(&keyword, &Some(value)),
         }
-        Ok(())
     }
 
-    pub fn resolve(&mut self, expr: &Expr, depth: usize) {}
+    /// Defines `name` in the globals, replacing any existing definition, so
+    /// a host can hand values and natives to a script before running it.
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.globals.borrow_mut().define(name.to_string(), value);
+    }
+
+    /// Reads a global back, e.g. after a script has run.
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.borrow().lookup(name)
+    }
+
+    /// Taken after `define_globals`, the snapshot lets a host run several
+    /// scripts in turn without re-registering the natives.
+    pub fn snapshot_globals(&self) -> GlobalsSnapshot {
+        GlobalsSnapshot {
+            globals: self.globals.borrow().clone(),
+            imported: self.imported.clone(),
+            modules: self.modules.clone(),
+        }
+    }
+
+    // The globals are replaced in place, so functions that captured them
+    // see the restored definitions too.
+    pub fn restore_globals(&mut self, snapshot: &GlobalsSnapshot) {
+        *self.globals.borrow_mut() = snapshot.globals.clone();
+        self.environment = Rc::clone(&self.globals);
+        self.imported = snapshot.imported.clone();
+        self.modules = snapshot.modules.clone();
+        self.call_stack.clear();
+    }
+
+    /// Records the script being run, so imports resolve relative to it and
+    /// importing it back is reported as a cycle.
+    pub fn enter_script(&mut self, path: &Path) {
+        if let Ok(file) = path.canonicalize() {
+            self.imported.insert(file.clone());
+            self.files.push(file);
+        }
+    }
+
+    // Failed calls leave their frames on the call stack, so by the time an
+    // error reaches the top level the stack still shows where it came from.
+    pub fn report_runtime(&self, line: usize, column: usize, message: &str) {
+        errors::report_runtime(line, column, message);
+        errors::report_backtrace(line, &self.call_stack);
+    }
+
+    pub fn resolve(&mut self, expr: &Expr, depth: usize) {
+        if let Expr::Variable { id, .. } | Expr::Assign { id, .. } = expr {
+            self.locals.insert(*id, depth);
+        }
+    }
+
+    /// Scope depths recorded by the resolver, keyed by expression id.
+    pub fn locals(&self) -> &HashMap<usize, usize> {
+        &self.locals
+    }
 
     pub fn visit_return_stmt(
         &mut self,
         _keyword: &Token,
         value: &Option<Expr>,
     ) -> Result<(), RuntimeError> {
-        let val = if let Some(expr) = value {
-            self.evaluate(expr)?
-        } else {
-            return Ok(());
+        let val = match value {
+            Some(Expr::Null) | None => Value::Nil,
+            Some(expr) => self.evaluate(expr)?,
         };
         Err(RuntimeError::Return(Return { value: val }))
     }
@@ -181,65 +430,338 @@ impl Evaluate {
     //     Ok(())
     // }
 
-    fn visit_function_stmt(&mut self, name: &Token, parameter: Vec<Token>, body: Vec<Stmt>) {
-        let function =
-            LoxFunction::new(name.clone(), parameter, body, Rc::clone(&self.environment));
+    // A plain import runs another file's top-level code in the global scope.
+    // With `as`, the file runs in its own scope and only its `export`ed
+    // declarations are reachable, through a module bound to the alias. Each
+    // file runs at most once per form; importing a file that is still being
+    // imported is a cycle.
+    fn visit_import_stmt(
+        &mut self,
+        keyword: &Token,
+        path: &str,
+        alias: Option<&Token>,
+    ) -> Result<(), RuntimeError> {
+        let error = |message: String| RuntimeError::Error {
+            message,
+            line: keyword.line,
+            token: keyword.clone(),
+        };
+        if self.sandbox {
+            return Err(error("Imports are disabled in sandbox mode.".to_string()));
+        }
+        let base = self
+            .files
+            .last()
+            .and_then(|file| file.parent())
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let file = base
+            .join(path)
+            .canonicalize()
+            .map_err(|_| error(format!("Can't read imported file '{}'.", path)))?;
+
+        if self.files.contains(&file) {
+            let mut cycle: Vec<String> = self
+                .files
+                .iter()
+                .skip_while(|active| **active != file)
+                .map(|active| active.display().to_string())
+                .collect();
+            cycle.push(file.display().to_string());
+            return Err(error(format!("Import cycle: {}.", cycle.join(" -> "))));
+        }
+
+        let Some(alias) = alias else {
+            if !self.imported.insert(file.clone()) {
+                return Ok(());
+            }
+            let statements = self
+                .load_file(&file)
+                .map_err(|source_error| error(import_error(path, source_error)))?;
+            self.files.push(file);
+            let result = self.execute_block(statements, Rc::clone(&self.globals));
+            self.files.pop();
+            return result;
+        };
+
+        let module = match self.modules.get(&file) {
+            Some(module) => Rc::clone(module),
+            None => {
+                let statements = self
+                    .load_file(&file)
+                    .map_err(|source_error| error(import_error(path, source_error)))?;
+                // The module's top-level scope stands in for the globals while
+                // it runs, and its functions keep it as theirs.
+                let env = Rc::new(RefCell::new(Environment::from_enclosing(Rc::clone(
+                    &self.globals,
+                ))));
+                let globals = std::mem::replace(&mut self.globals, Rc::clone(&env));
+                self.files.push(file.clone());
+                let result = self.execute_block(statements.clone(), Rc::clone(&env));
+                self.files.pop();
+                self.globals = globals;
+                result?;
+
+                let mut members = HashMap::new();
+                for statement in &statements {
+                    if let Stmt::Export(_, declaration) = statement {
+                        if let Stmt::Var(name, _) | Stmt::Function(name, ..) = declaration.as_ref()
+                        {
+                            members.insert(name.lexeme.clone(), env.borrow().get(name)?);
+                        }
+                    }
+                }
+                let name = file
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let module = Rc::new(Module { name, members });
+                self.modules.insert(file, Rc::clone(&module));
+                module
+            }
+        };
+        self.environment
+            .borrow_mut()
+            .define(alias.lexeme.clone(), Value::Module(module));
+        Ok(())
+    }
+
+    // Lexes, parses and resolves an imported file. Errors have already been
+    // reported when this exits, just as they would be for the main script.
+    fn load_file(&mut self, file: &Path) -> Result<Vec<Stmt>, SourceError> {
+        let source = read_source(file)?;
+        let (tokens, lex_error) = scan_tokens(&source);
+        let mut parser = Parser::new(tokens, true);
+        let statements = parser.parse_statements();
+        let mut resolver = Resolver::new(self);
+        resolver.resolve(&statements);
+        if lex_error || parser.had_error || resolver.had_error {
+            errors::exit(errors::EX_DATAERR);
+        }
+        Ok(statements)
+    }
+
+    fn visit_get_expr(&mut self, object: &Expr, name: &Token) -> Result<Value, RuntimeError> {
+        match self.evaluate(object)? {
+            Value::Module(module) => match module.members.get(&name.lexeme) {
+                Some(value) => Ok(value.clone()),
+                None => Err(RuntimeError::Error {
+                    message: format!("Module '{}' has no export '{}'.", module.name, name.lexeme),
+                    line: name.line,
+                    token: name.clone(),
+                }),
+            },
+            _ => Err(RuntimeError::Error {
+                message: "Only modules have properties.".to_string(),
+                line: name.line,
+                token: name.clone(),
+            }),
+        }
+    }
+
+    fn visit_function_stmt(
+        &mut self,
+        name: &Token,
+        parameter: Vec<Token>,
+        body: Vec<Stmt>,
+        return_type: Option<Token>,
+    ) -> Result<(), RuntimeError> {
+        let function = LoxFunction::new(
+            name.clone(),
+            parameter,
+            body,
+            return_type,
+            Rc::clone(&self.environment),
+            Rc::clone(&self.globals),
+        );
         self.environment
             .borrow_mut()
             .define(name.lexeme.clone(), Value::Function(Rc::new(function)));
+        Ok(())
     }
 
     fn visit_call_expr(
         &mut self,
-        callee: &Box<Expr>,
+        callee: &Expr,
         paren: &Token,
         arguments: &Vec<Expr>,
     ) -> Result<Value, RuntimeError> {
-        let callee = self.evaluate(&callee)?;
+        // `paren` is the closing parenthesis, which may be several lines
+        // below the callee when the arguments span lines.
+        let line = callee.line().unwrap_or(paren.line);
+        // Natives don't know the name they were registered under, so their
+        // frames use the name the callee was looked up by.
+        let callee_name = match callee {
+            Expr::Variable { name, .. } => Some(name.lexeme.clone()),
+            _ => None,
+        };
+        let callee = self.evaluate(callee)?;
 
         let mut evaluated_args = Vec::new();
         for arg in arguments {
-            let arg_value = self.evaluate(&arg)?;
+            let arg_value = self.evaluate(arg)?;
             evaluated_args.push(arg_value);
         }
 
         match callee {
             Value::Function(function) => {
-                if arguments.len() != function.arity() {
-                    return Err(RuntimeError::Error {
-                        message: format!(
-                            "Expected {} arguments but got {}.",
-                            function.arity(),
-                            arguments.len()
-                        ),
-                        line: paren.line,
-                        token: paren.clone(),
-                    });
-                }
-                return function.call(self, evaluated_args);
+                self.call_function(function, callee_name, line, paren, evaluated_args)
             }
             _ => Err(RuntimeError::Error {
                 message: "Can only call functions and classes.".to_string(),
-                line: paren.line,
+                line,
                 token: paren.clone(),
             }),
         }
     }
 
-    fn visit_while_stmt(&mut self, condition: &Expr, body: &Stmt) -> Result<(), RuntimeError> {
+    /// Checks the arity, then runs the call under a new call-stack frame.
+    /// `fallback_name` names the frame for callables without a name of
+    /// their own. Natives that take a callback, like `sort`, call it
+    /// through here so it gets the same checks and backtraces.
+    pub fn call_function(
+        &mut self,
+        function: Rc<dyn LoxCallable>,
+        fallback_name: Option<String>,
+        line: usize,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        if function.variadic() && arguments.len() < function.arity() {
+            return Err(RuntimeError::Error {
+                message: format!(
+                    "Expected at least {} arguments but got {}.",
+                    function.arity(),
+                    arguments.len()
+                ),
+                line,
+                token: paren.clone(),
+            });
+        }
+        if !function.variadic() && arguments.len() != function.arity() {
+            return Err(RuntimeError::Error {
+                message: format!(
+                    "Expected {} arguments but got {}.",
+                    function.arity(),
+                    arguments.len()
+                ),
+                line,
+                token: paren.clone(),
+            });
+        }
+        if self.call_stack.len() >= MAX_CALL_DEPTH {
+            return Err(RuntimeError::Error {
+                message: "Stack overflow.".to_string(),
+                line,
+                token: paren.clone(),
+            });
+        }
+        self.call_stack.push(Frame {
+            name: function
+                .name()
+                .or(fallback_name)
+                .unwrap_or_else(|| function.to_string()),
+            call_line: line,
+        });
+        let result = function.call(self, paren, arguments);
+        // A failed call keeps its frame so the error reported further up
+        // still shows where it came from.
+        if result.is_ok() {
+            self.call_stack.pop();
+        }
+        result
+    }
+
+    fn visit_while_stmt(
+        &mut self,
+        keyword: &Token,
+        condition: &Expr,
+        body: &Stmt,
+        increment: Option<&Expr>,
+    ) -> Result<(), RuntimeError> {
+        let mut iterations = 0;
         loop {
-            let cond_val = self.evaluate(condition);
-            match cond_val {
-                Ok(val) => {
-                    if self.is_truthy(&val) {
-                        let _ = self.execute(body.clone(), true);
-                    } else {
-                        return Ok(());
-                    }
-                }
-                Err(error) => return Err(error),
+            let cond_val = self.evaluate(condition)?;
+            if !self.is_truthy(&cond_val) {
+                return Ok(());
+            }
+            self.count_iteration(&mut iterations, keyword)?;
+            if !self.execute_loop_body(body)? {
+                return Ok(());
+            }
+            if let Some(increment) = increment {
+                self.evaluate(increment)?;
+            }
+        }
+    }
+
+    fn visit_do_while_stmt(
+        &mut self,
+        keyword: &Token,
+        body: &Stmt,
+        condition: &Expr,
+    ) -> Result<(), RuntimeError> {
+        let mut iterations = 0;
+        loop {
+            self.count_iteration(&mut iterations, keyword)?;
+            if !self.execute_loop_body(body)? {
+                return Ok(());
+            }
+            let cond_val = self.evaluate(condition)?;
+            if !self.is_truthy(&cond_val) {
+                return Ok(());
+            }
+        }
+    }
+
+    // Counts one more pass through a loop's body against `max_iterations`.
+    // The error is reported at the loop's `while`, `for` or `do` keyword.
+    fn count_iteration(&self, iterations: &mut usize, keyword: &Token) -> Result<(), RuntimeError> {
+        *iterations += 1;
+        match self.max_iterations {
+            Some(max) if *iterations > max => Err(RuntimeError::Error {
+                message: "Loop iteration limit exceeded.".to_string(),
+                token: keyword.clone(),
+                line: keyword.line,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    // Runs the first case equal to the subject, or the default. Cases don't
+    // fall through, and `break` leaves the switch early.
+    fn visit_switch_stmt(
+        &mut self,
+        subject: &Expr,
+        cases: Vec<(Expr, Vec<Stmt>)>,
+        default: Option<Vec<Stmt>>,
+    ) -> Result<(), RuntimeError> {
+        let subject = self.evaluate(subject)?;
+        let mut body = default;
+        for (value, case_body) in cases {
+            let value = self.evaluate(&value)?;
+            if self.is_equal(&subject, &value) {
+                body = Some(case_body);
+                break;
             }
         }
+        let Some(body) = body else {
+            return Ok(());
+        };
+        match self.visit_block_stmt(body) {
+            Err(RuntimeError::Break) => Ok(()),
+            result => result,
+        }
+    }
+
+    // Runs one iteration of a loop body, returning whether the loop should
+    // keep going.
+    fn execute_loop_body(&mut self, body: &Stmt) -> Result<bool, RuntimeError> {
+        match self.execute(body.clone(), false) {
+            Ok(()) | Err(RuntimeError::Continue) => Ok(true),
+            Err(RuntimeError::Break) => Ok(false),
+            Err(error) => Err(error),
+        }
     }
 
     fn visit_logical_expr(
@@ -265,6 +787,10 @@ impl Evaluate {
                     self.evaluate(right)
                 }
             }
+            TokenType::QUESTION_QUESTION => match left {
+                Value::Nil => self.evaluate(right),
+                _ => Ok(left),
+            },
             _ => Err(RuntimeError::Error {
                 message: "Unknown logical operator".to_string(),
                 line: operator.line,
@@ -279,94 +805,79 @@ impl Evaluate {
         then_branch: Stmt,
         else_branch: Option<Stmt>,
     ) -> Result<(), RuntimeError> {
-        match self.evaluate(&condition) {
-            Ok(condition_val) => {
-                if self.is_truthy(&condition_val) {
-                    self.execute(then_branch, false)
-                } else if let Some(stmt) = else_branch {
-                    self.execute(stmt, false)
-                } else {
-                    Ok(())
-                }
-            }
-            Err(error) => match error {
-                RuntimeError::Error {
-                    message,
-                    line,
-                    token,
-                } => {
-                    let _ = token;
-                    writeln!(io::stderr(), "[line {}] Runtime Error: {}", line, message).unwrap();
-                    std::process::exit(70)
-                }
-                RuntimeError::Return(ret) => Err(RuntimeError::Return(ret)),
-            },
+        let condition_val = self.evaluate(&condition)?;
+        if self.is_truthy(&condition_val) {
+            self.execute(then_branch, false)
+        } else if let Some(stmt) = else_branch {
+            self.execute(stmt, false)
+        } else {
+            Ok(())
         }
     }
 
-    fn visit_var_stmt(&mut self, expr: &Expr, name: &Token) {
+    fn visit_var_stmt(&mut self, expr: &Expr, name: &Token) -> Result<(), RuntimeError> {
         let mut value = Value::Nil;
         if !matches!(expr, Expr::Null) {
-            match self.evaluate(expr) {
-                Ok(val) => value = val,
-                Err(error) => match error {
-                    RuntimeError::Error {
-                        message,
-                        line,
-                        token,
-                    } => {
-                        let _ = token;
-                        writeln!(io::stderr(), "[line {}] Runtime Error: {}", line, message)
-                            .unwrap();
-                        std::process::exit(70)
-                    }
-                    _ => return,
-                },
-            }
+            value = self.evaluate(expr)?;
         }
         self.environment
             .borrow_mut()
             .define(name.lexeme.clone(), value);
+        Ok(())
     }
 
-    fn visit_variable_expr(&self, name: Token) -> Result<Value, RuntimeError> {
-        self.environment.borrow_mut().get(name)
+    // Locals are found at the depth the resolver recorded; anything it left
+    // unresolved is a global.
+    fn visit_variable_expr(&self, id: usize, name: &Token) -> Result<Value, RuntimeError> {
+        match self.locals.get(&id) {
+            Some(&distance) => Environment::get_at(&self.environment, distance, name),
+            None => match self.globals.borrow().get(name) {
+                Err(RuntimeError::Error { .. }) if self.lenient => {
+                    errors::report_warning(
+                        name.line,
+                        name.column,
+                        &format!("undefined variable '{}' read as nil.", name.lexeme),
+                    );
+                    Ok(Value::Nil)
+                }
+                result => result,
+            },
+        }
     }
 
-    fn visit_assign_expr(&mut self, expr: &Expr, name: Token) -> Result<Value, RuntimeError> {
-        let value = self.evaluate(expr);
-        match value {
-            Ok(value) => {
-                match self.environment.borrow_mut().assign(name, value.clone()) {
-                    Ok(_) => return Ok(value),
-                    Err(err) => return Err(err),
-                };
+    fn visit_assign_expr(
+        &mut self,
+        id: usize,
+        expr: &Expr,
+        name: &Token,
+    ) -> Result<Value, RuntimeError> {
+        let value = self.evaluate(expr)?;
+        match self.locals.get(&id) {
+            Some(&distance) => {
+                Environment::assign_at(&self.environment, distance, name, value.clone())?
             }
-            Err(err) => return Err(err),
+            None => self.globals.borrow_mut().assign(name, value.clone())?,
         }
+        Ok(value)
     }
 
     pub fn visit_expression_stmt(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
         self.evaluate(expr)
     }
 
-    fn visit_print_stmt(&mut self, expr: &Expr) {
-        let value = self.evaluate(expr);
-        match value {
-            Ok(v) => println!("{}", v),
-            Err(error) => match error {
-                RuntimeError::Error {
-                    message,
-                    line,
-                    token,
-                } => {
-                    let _ = token;
-                    writeln!(io::stderr(), "[line {}] Runtime Error: {}", line, message).unwrap();
-                    std::process::exit(70)
-                }
-                _ => return,
-            },
-        }
+    fn visit_print_stmt(&mut self, exprs: &[Expr]) -> Result<(), RuntimeError> {
+        let values = exprs
+            .iter()
+            .map(|expr| self.evaluate(expr).map(|v| v.to_string()))
+            .collect::<Result<Vec<String>, RuntimeError>>()?;
+        println!("{}", values.join(" "));
+        Ok(())
+    }
+
+    /// Evaluates a standalone expression, such as one from
+    /// `Parser::parse_expression`. Variables in it are looked up as globals.
+    pub fn eval_expr(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        self.evaluate(expr)
     }
 
     fn evaluate(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
@@ -379,6 +890,19 @@ impl Evaluate {
                 _ => Ok(Value::Nil),
             },
             Expr::Grouping { expression } => self.evaluate(expression),
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let condition = self.evaluate(condition)?;
+                if self.is_truthy(&condition) {
+                    self.evaluate(then_branch)
+                } else {
+                    self.evaluate(else_branch)
+                }
+            }
             Expr::Unary { operator, right } => {
                 let right = self.evaluate(right)?;
 
@@ -402,8 +926,9 @@ impl Evaluate {
                     }),
                 }
             }
-            Expr::Variable { name } => self.visit_variable_expr(name.clone()),
-            Expr::Assign { name, value } => self.visit_assign_expr(value, name.clone()),
+            Expr::Variable { id, name } => self.visit_variable_expr(*id, name),
+            Expr::Get { object, name } => self.visit_get_expr(object, name),
+            Expr::Assign { id, name, value } => self.visit_assign_expr(*id, value, name),
             Expr::Logical {
                 left,
                 operator,
@@ -413,7 +938,14 @@ impl Evaluate {
                 callee,
                 paren,
                 arguments,
-            } => self.visit_call_expr(&callee, &paren, &arguments),
+            } => self.visit_call_expr(callee, paren, arguments),
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } if is_comparison(operator) => self
+                .evaluate_comparison(left, operator, right)
+                .map(|(result, _)| result),
             Expr::Binary {
                 left,
                 operator,
@@ -430,6 +962,18 @@ impl Evaluate {
                         self.number_operation(&left, &right, |a, b| a / b, operator)
                     }
                     TokenType::STAR => self.number_operation(&left, &right, |a, b| a * b, operator),
+                    // Floor division; unlike `/` it refuses to divide by zero
+                    // rather than producing an infinity.
+                    TokenType::DIV => match (&left, &right) {
+                        (Value::Number(_), Value::Number(b)) if *b == 0.0 => {
+                            Err(RuntimeError::Error {
+                                message: "Division by zero.".to_string(),
+                                token: operator.clone(),
+                                line: operator.line,
+                            })
+                        }
+                        _ => self.number_operation(&left, &right, |a, b| (a / b).floor(), operator),
+                    },
                     TokenType::PLUS => match (&left, &right) {
                         (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
                         (Value::String(a), Value::String(b)) => {
@@ -441,16 +985,6 @@ impl Evaluate {
                             line: operator.line,
                         }),
                     },
-                    TokenType::GREATER => {
-                        self.number_operation(&left, &right, |a, b| a > b, operator)
-                    }
-                    TokenType::GREATER_EQUAL => {
-                        self.number_operation(&left, &right, |a, b| a >= b, operator)
-                    }
-                    TokenType::LESS => self.number_operation(&left, &right, |a, b| a < b, operator),
-                    TokenType::LESS_EQUAL => {
-                        self.number_operation(&left, &right, |a, b| a <= b, operator)
-                    }
                     TokenType::BANG_EQUAL => Ok(Value::Boolean(!self.is_equal(&left, &right))),
                     TokenType::EQUAL_EQUAL => Ok(Value::Boolean(self.is_equal(&left, &right))),
                     _ => Err(RuntimeError::Error {
@@ -466,6 +1000,7 @@ impl Evaluate {
                     token_type: TokenType::NIL,
                     lexeme: String::new(),
                     line: 0,
+                    column: 0,
                     literal: Literal::None,
                 },
                 line: 0,
@@ -473,6 +1008,44 @@ impl Evaluate {
         }
     }
 
+    /// Comparisons chain like Python: `a < b < c` means `a < b and b < c`,
+    /// with `b` evaluated once and `c` skipped once the chain is false. A
+    /// parenthesized comparison is an ordinary operand, so `(a < b) < c`
+    /// still compares a boolean with `c` and fails. Returns the result along
+    /// with the right operand's value for the next link in the chain.
+    fn evaluate_comparison(
+        &mut self,
+        left: &Expr,
+        operator: &Token,
+        right: &Expr,
+    ) -> Result<(Value, Value), RuntimeError> {
+        let left = match left {
+            Expr::Binary {
+                left: inner_left,
+                operator: inner_operator,
+                right: inner_right,
+            } if is_comparison(inner_operator) => {
+                let (result, middle) =
+                    self.evaluate_comparison(inner_left, inner_operator, inner_right)?;
+                if !self.is_truthy(&result) {
+                    return Ok((result, middle));
+                }
+                middle
+            }
+            _ => self.evaluate(left)?,
+        };
+        let right = self.evaluate(right)?;
+        let result = match operator.token_type {
+            TokenType::GREATER => self.number_operation(&left, &right, |a, b| a > b, operator),
+            TokenType::GREATER_EQUAL => {
+                self.number_operation(&left, &right, |a, b| a >= b, operator)
+            }
+            TokenType::LESS => self.number_operation(&left, &right, |a, b| a < b, operator),
+            _ => self.number_operation(&left, &right, |a, b| a <= b, operator),
+        }?;
+        Ok((result, right))
+    }
+
     fn number_operation<T, F>(
         &self,
         left: &Value,
@@ -502,17 +1075,35 @@ impl Evaluate {
         }
     }
 
-    fn is_equal(&self, a: &Value, b: &Value) -> bool {
+    /// `==` never fails: values of different types, such as `1` and `"1"`,
+    /// are simply unequal. Numbers compare exactly, as IEEE 754 does, so
+    /// `0.1 + 0.2 == 0.3` is false and NaN is unequal even to itself; use
+    /// `approxEq` to allow for rounding.
+    pub fn is_equal(&self, a: &Value, b: &Value) -> bool {
         match (a, b) {
             (Value::Nil, Value::Nil) => true,
             (Value::Boolean(a), Value::Boolean(b)) => a == b,
-            (Value::Number(a), Value::Number(b)) => (a - b).abs() < f64::EPSILON,
+            (Value::Number(a), Value::Number(b)) => a == b,
             (Value::String(a), Value::String(b)) => a == b,
+            // Functions, lists and modules are reference values: two are
+            // equal only when they are the same object, so re-running a
+            // `fun` declaration gives a new value that is unequal to the old
+            // one, and two lists with the same elements are still unequal.
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+            (Value::Module(a), Value::Module(b)) => Rc::ptr_eq(a, b),
             _ => false,
         }
     }
 }
 
+fn is_comparison(operator: &Token) -> bool {
+    matches!(
+        operator.token_type,
+        TokenType::GREATER | TokenType::GREATER_EQUAL | TokenType::LESS | TokenType::LESS_EQUAL
+    )
+}
+
 impl From<bool> for Value {
     fn from(b: bool) -> Self {
         Value::Boolean(b)
@@ -525,28 +1116,109 @@ impl From<f64> for Value {
     }
 }
 
-pub fn evaluate(filename: &str, flag: bool) {
-    let file_contents = match fs::read_to_string(filename) {
-        Ok(contents) => contents,
-        Err(_) => {
-            writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
-            return;
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl Value {
+    /// The name of the value's type, as used in conversion errors.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Boolean(_) => "boolean",
+            Value::Nil => "nil",
+            Value::Function(_) => "function",
+            Value::List(_) => "list",
+            Value::Module(_) => "module",
+        }
+    }
+}
+
+/// Returned when a `Value` is converted to a Rust type it doesn't hold.
+#[derive(Debug)]
+pub struct ConversionError {
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Expected a {} but got {}.", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+impl TryFrom<Value> for f64 {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(n) => Ok(n),
+            other => Err(ConversionError {
+                expected: "number",
+                found: other.type_name(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(s) => Ok(s),
+            other => Err(ConversionError {
+                expected: "string",
+                found: other.type_name(),
+            }),
         }
-    };
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = ConversionError;
 
-    if file_contents.is_empty() {
-        println!("EOF  null");
-        return;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Boolean(b) => Ok(b),
+            other => Err(ConversionError {
+                expected: "boolean",
+                found: other.type_name(),
+            }),
+        }
     }
+}
 
-    let mut parser = Parser::new(return_tokens(&file_contents), !flag);
-    let mut evaluate = Evaluate::new();
-    evaluate.define_globals();
-    let statement = parser.parse();
-    for stmt in statement {
-        let _ = evaluate.execute(stmt, flag);
+fn import_error(path: &str, source_error: SourceError) -> String {
+    match source_error {
+        SourceError::Unreadable => format!("Can't read imported file '{}'.", path),
+        SourceError::InvalidUtf8(byte) => {
+            format!(
+                "Imported file '{}' is not valid UTF-8 at byte {}.",
+                path, byte
+            )
+        }
     }
-    if parser.had_error && !flag {
-        std::process::exit(65);
+}
+
+// Prints the first line of the statement's source, so a block or function
+// shows only its header rather than its whole body.
+fn trace_stmt(stmt: &Stmt) {
+    let source = stmt.to_string();
+    let header = source.lines().next().unwrap_or("");
+    match stmt.line() {
+        Some(line) => eprintln!("[line {}] {}", line, header),
+        None => eprintln!("{}", header),
     }
 }
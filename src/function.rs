@@ -4,22 +4,1319 @@ use crate::{
     evaluate::{Evaluate, RuntimeError, Value},
     parse::Stmt,
 };
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::fs;
 use std::rc::Rc;
 pub trait LoxCallable {
     fn arity(&self) -> usize;
     fn call(
         &self,
         interpreter: &mut Evaluate,
+        paren: &Token,
         arguments: Vec<Value>,
     ) -> Result<Value, RuntimeError>;
     fn to_string(&self) -> String;
+    /// Variadic callables take `arity()` or more arguments.
+    fn variadic(&self) -> bool {
+        false
+    }
+    /// Name shown in call-stack backtraces. Natives have none of their own.
+    fn name(&self) -> Option<String> {
+        None
+    }
+}
+
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+pub struct Clock;
+
+impl LoxCallable for Clock {
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        _paren: &Token,
+        _arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let start = SystemTime::now();
+        let since_epoch = start.duration_since(UNIX_EPOCH).unwrap();
+        Ok(Value::Number((since_epoch.as_millis() / 1000) as f64))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// `benchmark(f, n)` calls the zero-argument function `f` `n` times and
+/// returns the total elapsed time in seconds.
+pub struct Benchmark;
+
+impl LoxCallable for Benchmark {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let Value::Function(function) = &arguments[0] else {
+            return Err(native_error(paren, "Can only benchmark a function."));
+        };
+        if function.arity() != 0 {
+            return Err(native_error(
+                paren,
+                "Can only benchmark a function that takes no arguments.",
+            ));
+        }
+        let iterations = integer_arg(&arguments[1], paren)?;
+        if iterations < 0 {
+            return Err(native_error(paren, "Iterations must not be negative."));
+        }
+        let start = Instant::now();
+        for _ in 0..iterations {
+            interpreter.call_function(Rc::clone(function), None, paren.line, paren, Vec::new())?;
+        }
+        Ok(Value::Number(start.elapsed().as_secs_f64()))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+fn native_error(paren: &Token, message: &str) -> RuntimeError {
+    RuntimeError::Error {
+        message: message.to_string(),
+        line: paren.line,
+        token: paren.clone(),
+    }
+}
+
+fn integer_arg(value: &Value, paren: &Token) -> Result<i64, RuntimeError> {
+    match value {
+        Value::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => Ok(*n as i64),
+        _ => Err(native_error(paren, "Operand must be an integer.")),
+    }
+}
+
+fn number_arg(value: &Value, paren: &Token) -> Result<f64, RuntimeError> {
+    match value {
+        Value::Number(n) => Ok(*n),
+        _ => Err(native_error(paren, "Operands must be numbers.")),
+    }
+}
+
+fn list_arg<'a>(
+    value: &'a Value,
+    paren: &Token,
+) -> Result<&'a Rc<RefCell<Vec<Value>>>, RuntimeError> {
+    match value {
+        Value::List(values) => Ok(values),
+        _ => Err(native_error(paren, "Operand must be a list.")),
+    }
+}
+
+// A position in a list of `len` elements; `len` itself is only valid when
+// `allow_end` is set, as for inserting at the end.
+fn list_index_arg(
+    value: &Value,
+    len: usize,
+    allow_end: bool,
+    paren: &Token,
+) -> Result<usize, RuntimeError> {
+    let index = integer_arg(value, paren)?;
+    match usize::try_from(index) {
+        Ok(index) if index < len || (allow_end && index == len) => Ok(index),
+        _ => Err(native_error(paren, "List index out of range.")),
+    }
+}
+
+fn string_arg<'a>(value: &'a Value, paren: &Token) -> Result<&'a str, RuntimeError> {
+    match value {
+        Value::String(s) => Ok(s),
+        _ => Err(native_error(paren, "Operand must be a string.")),
+    }
+}
+
+/// `readFile(path)` returns the whole file as a string. Relative paths are
+/// taken from the working directory, not the script's.
+pub struct ReadFile;
+
+impl LoxCallable for ReadFile {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let path = string_arg(&arguments[0], paren)?;
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(Value::String(contents)),
+            Err(error) => Err(native_error(
+                paren,
+                &format!("Could not read file '{}': {}.", path, error),
+            )),
+        }
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// `writeFile(path, contents)` replaces the file's contents with the
+/// string, creating the file if needed.
+pub struct WriteFile;
+
+impl LoxCallable for WriteFile {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let path = string_arg(&arguments[0], paren)?;
+        let contents = string_arg(&arguments[1], paren)?;
+        match fs::write(path, contents) {
+            Ok(()) => Ok(Value::Nil),
+            Err(error) => Err(native_error(
+                paren,
+                &format!("Could not write file '{}': {}.", path, error),
+            )),
+        }
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+pub struct Hex;
+
+impl LoxCallable for Hex {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let n = integer_arg(&arguments[0], paren)?;
+        let sign = if n < 0 { "-" } else { "" };
+        Ok(Value::String(format!("{}0x{:x}", sign, n.unsigned_abs())))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// `toFixed(n, digits)` formats `n` with exactly `digits` decimal places,
+/// like JavaScript's `Number.prototype.toFixed`, with `digits` from 0 to
+/// 100. Unlike JavaScript, a value exactly halfway rounds to even, so
+/// `toFixed(2.5, 0)` is `"2"`.
+pub struct ToFixed;
+
+impl LoxCallable for ToFixed {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let n = number_arg(&arguments[0], paren)?;
+        let digits = integer_arg(&arguments[1], paren)?;
+        if !(0..=100).contains(&digits) {
+            return Err(native_error(paren, "Digits must be between 0 and 100."));
+        }
+        if !n.is_finite() {
+            return Ok(Value::String(Value::Number(n).to_string()));
+        }
+        Ok(Value::String(format!("{:.*}", digits as usize, n)))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+pub struct Bin;
+
+impl LoxCallable for Bin {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let n = integer_arg(&arguments[0], paren)?;
+        let sign = if n < 0 { "-" } else { "" };
+        Ok(Value::String(format!("{}0b{:b}", sign, n.unsigned_abs())))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+pub struct Substring;
+
+impl LoxCallable for Substring {
+    fn arity(&self) -> usize {
+        3
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let s = string_arg(&arguments[0], paren)?;
+        let start = integer_arg(&arguments[1], paren)?;
+        let end = integer_arg(&arguments[2], paren)?;
+        let len = s.chars().count() as i64;
+        if start < 0 || end > len || start > end {
+            return Err(native_error(paren, "Substring indices out of range."));
+        }
+        Ok(Value::String(
+            s.chars()
+                .skip(start as usize)
+                .take((end - start) as usize)
+                .collect(),
+        ))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+pub struct IndexOf;
+
+impl LoxCallable for IndexOf {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let s = string_arg(&arguments[0], paren)?;
+        let needle = string_arg(&arguments[1], paren)?;
+        // Report a character index rather than a byte offset.
+        let index = match s.find(needle) {
+            Some(byte) => s[..byte].chars().count() as f64,
+            None => -1.0,
+        };
+        Ok(Value::Number(index))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+pub struct Contains;
+
+impl LoxCallable for Contains {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let s = string_arg(&arguments[0], paren)?;
+        let needle = string_arg(&arguments[1], paren)?;
+        Ok(Value::Boolean(s.contains(needle)))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+pub struct Split;
+
+impl LoxCallable for Split {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let s = string_arg(&arguments[0], paren)?;
+        let separator = string_arg(&arguments[1], paren)?;
+        if separator.is_empty() {
+            return Err(native_error(paren, "Separator must not be empty."));
+        }
+        let parts = s
+            .split(separator)
+            .map(|part| Value::String(part.to_string()))
+            .collect();
+        Ok(Value::List(Rc::new(RefCell::new(parts))))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// Joins the elements of a list with a separator. Elements that are not
+/// strings are stringified the same way `print` would show them.
+pub struct Join;
+
+impl LoxCallable for Join {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let separator = string_arg(&arguments[1], paren)?;
+        match &arguments[0] {
+            Value::List(values) => {
+                let parts: Vec<String> = values.borrow().iter().map(|v| v.to_string()).collect();
+                Ok(Value::String(parts.join(separator)))
+            }
+            _ => Err(native_error(paren, "Operand must be a list.")),
+        }
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// `format("{} + {} = {}", a, b, c)`: each `{}` takes the next argument,
+/// `{{` and `}}` stand for literal braces.
+pub struct Format;
+
+impl LoxCallable for Format {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let template = string_arg(&arguments[0], paren)?;
+        let mut values = arguments[1..].iter();
+        let mut result = String::new();
+        let mut placeholders = 0;
+        let mut chars = template.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match (ch, chars.peek()) {
+                ('{', Some('{')) | ('}', Some('}')) => {
+                    chars.next();
+                    result.push(ch);
+                }
+                ('{', Some('}')) => {
+                    chars.next();
+                    placeholders += 1;
+                    if let Some(value) = values.next() {
+                        result.push_str(&value.to_string());
+                    }
+                }
+                ('{', _) | ('}', _) => {
+                    return Err(native_error(
+                        paren,
+                        &format!("Unmatched '{}' in format string.", ch),
+                    ))
+                }
+                _ => result.push(ch),
+            }
+        }
+        if placeholders != arguments.len() - 1 {
+            return Err(native_error(
+                paren,
+                &format!(
+                    "Format string has {} placeholders but got {} arguments.",
+                    placeholders,
+                    arguments.len() - 1
+                ),
+            ));
+        }
+        Ok(Value::String(result))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+
+    fn variadic(&self) -> bool {
+        true
+    }
+}
+
+/// `push(list, value)` appends in place and returns the list.
+pub struct Push;
+
+impl LoxCallable for Push {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let list = list_arg(&arguments[0], paren)?;
+        list.borrow_mut().push(arguments[1].clone());
+        Ok(arguments[0].clone())
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// `pop(list)` removes and returns the last element.
+pub struct Pop;
+
+impl LoxCallable for Pop {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let list = list_arg(&arguments[0], paren)?;
+        let popped = list.borrow_mut().pop();
+        popped.ok_or_else(|| native_error(paren, "Can't pop from an empty list."))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// `insert(list, index, value)` inserts before `index` and returns the list.
+pub struct Insert;
+
+impl LoxCallable for Insert {
+    fn arity(&self) -> usize {
+        3
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let list = list_arg(&arguments[0], paren)?;
+        let index = list_index_arg(&arguments[1], list.borrow().len(), true, paren)?;
+        list.borrow_mut().insert(index, arguments[2].clone());
+        Ok(arguments[0].clone())
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// `remove(list, index)` removes and returns the element at `index`.
+pub struct Remove;
+
+impl LoxCallable for Remove {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let list = list_arg(&arguments[0], paren)?;
+        let index = list_index_arg(&arguments[1], list.borrow().len(), false, paren)?;
+        let removed = list.borrow_mut().remove(index);
+        Ok(removed)
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// `reverse(list)` returns a new list with the elements in reverse order.
+pub struct Reverse;
+
+impl LoxCallable for Reverse {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let list = list_arg(&arguments[0], paren)?;
+        let reversed: Vec<Value> = list.borrow().iter().rev().cloned().collect();
+        Ok(Value::List(Rc::new(RefCell::new(reversed))))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// `sort(list)` returns a new list sorted ascending; the list must hold
+/// only numbers or only strings. `sort(list, compare)` instead orders by
+/// `compare(a, b)`, which returns a negative number when `a` goes first,
+/// a positive one when `b` does, and 0 when they tie. The sort is stable,
+/// and stops at the first comparator error or NaN result.
+pub struct Sort;
+
+impl LoxCallable for Sort {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        if arguments.len() > 2 {
+            return Err(native_error(
+                paren,
+                &format!("Expected at most 2 arguments but got {}.", arguments.len()),
+            ));
+        }
+        let mut values = list_arg(&arguments[0], paren)?.borrow().clone();
+        match arguments.get(1) {
+            None => sort_natural(&mut values, paren)?,
+            Some(Value::Function(compare)) => {
+                values = merge_sort(values, &mut |a, b| {
+                    let result = interpreter.call_function(
+                        Rc::clone(compare),
+                        None,
+                        paren.line,
+                        paren,
+                        vec![a.clone(), b.clone()],
+                    )?;
+                    match result {
+                        Value::Number(n) if n.is_nan() => {
+                            Err(native_error(paren, "Comparator returned NaN."))
+                        }
+                        Value::Number(n) => Ok(n > 0.0),
+                        _ => Err(native_error(paren, "Comparator must return a number.")),
+                    }
+                })?;
+            }
+            Some(_) => return Err(native_error(paren, "Comparator must be a function.")),
+        }
+        Ok(Value::List(Rc::new(RefCell::new(values))))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+
+    fn variadic(&self) -> bool {
+        true
+    }
+}
+
+/// `copy(value)` deep-copies a list, including any lists nested in it, so
+/// changes to the copy never show in the original. Other values are
+/// returned as they are.
+pub struct DeepCopy;
+
+impl LoxCallable for DeepCopy {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        _paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        Ok(deep_copy(&arguments[0], &mut Vec::new()))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+// `copied` pairs each list already copied with its copy, so a list reached
+// twice is copied once and a list that contains itself doesn't recurse
+// forever; the copy keeps the same sharing.
+fn deep_copy(value: &Value, copied: &mut Vec<(Rc<RefCell<Vec<Value>>>, Value)>) -> Value {
+    let Value::List(list) = value else {
+        return value.clone();
+    };
+    if let Some((_, copy)) = copied
+        .iter()
+        .find(|(original, _)| Rc::ptr_eq(original, list))
+    {
+        return copy.clone();
+    }
+    let copy = Rc::new(RefCell::new(Vec::new()));
+    copied.push((Rc::clone(list), Value::List(Rc::clone(&copy))));
+    let elements: Vec<Value> = list
+        .borrow()
+        .iter()
+        .map(|element| deep_copy(element, copied))
+        .collect();
+    *copy.borrow_mut() = elements;
+    Value::List(copy)
+}
+
+// A stable merge sort over a comparator that can fail. `greater(a, b)`
+// says whether `b` must go before `a`. The first error stops the sort.
+// Unlike `sort_by`, it never panics when the comparator isn't a total
+// order; the list just comes back in some order the comparator allowed.
+fn merge_sort(
+    mut values: Vec<Value>,
+    greater: &mut impl FnMut(&Value, &Value) -> Result<bool, RuntimeError>,
+) -> Result<Vec<Value>, RuntimeError> {
+    if values.len() <= 1 {
+        return Ok(values);
+    }
+    let right = values.split_off(values.len() / 2);
+    let mut left = merge_sort(values, greater)?.into_iter().peekable();
+    let mut right = merge_sort(right, greater)?.into_iter().peekable();
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        let next = if greater(a, b)? {
+            right.next()
+        } else {
+            left.next()
+        };
+        merged.extend(next);
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+fn sort_natural(values: &mut [Value], paren: &Token) -> Result<(), RuntimeError> {
+    if values.iter().all(|value| matches!(value, Value::Number(_))) {
+        values.sort_by(|a, b| match (a, b) {
+            (Value::Number(a), Value::Number(b)) => a.total_cmp(b),
+            _ => Ordering::Equal,
+        });
+    } else if values.iter().all(|value| matches!(value, Value::String(_))) {
+        values.sort_by(|a, b| match (a, b) {
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            _ => Ordering::Equal,
+        });
+    } else {
+        return Err(native_error(
+            paren,
+            "Can only sort a list of all numbers or all strings.",
+        ));
+    }
+    Ok(())
+}
+
+pub struct Upper;
+
+impl LoxCallable for Upper {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let s = string_arg(&arguments[0], paren)?;
+        Ok(Value::String(s.to_uppercase()))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+pub struct Lower;
+
+impl LoxCallable for Lower {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let s = string_arg(&arguments[0], paren)?;
+        Ok(Value::String(s.to_lowercase()))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+pub struct Trim;
+
+impl LoxCallable for Trim {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let s = string_arg(&arguments[0], paren)?;
+        Ok(Value::String(s.trim().to_string()))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// Marsaglia's xorshift64 generator shared by `random`, `randomInt` and
+/// `seed`. `random()` takes the top 53 bits of each output, so it is uniform
+/// over `[0, 1)`; `randomInt(lo, hi)` reduces an output modulo `hi - lo`.
+/// It is fast and reproducible, not cryptographically secure.
+pub struct Xorshift {
+    state: Cell<u64>,
+}
+
+impl Xorshift {
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        Xorshift::from_seed(nanos)
+    }
+
+    pub fn from_seed(seed: u64) -> Self {
+        let rng = Xorshift {
+            state: Cell::new(0),
+        };
+        rng.seed(seed);
+        rng
+    }
+
+    /// Scrambles the seed with splitmix64 so that small seeds don't give
+    /// near-zero first outputs, and so the state is never zero.
+    fn seed(&self, seed: u64) {
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        self.state.set(if z == 0 { 1 } else { z });
+    }
+
+    fn next_u64(&self) -> u64 {
+        let mut x = self.state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state.set(x);
+        x
+    }
+
+    fn next_f64(&self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+pub struct Random {
+    rng: Rc<Xorshift>,
+}
+
+impl Random {
+    pub fn new(rng: Rc<Xorshift>) -> Self {
+        Random { rng }
+    }
+}
+
+impl LoxCallable for Random {
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        _paren: &Token,
+        _arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        Ok(Value::Number(self.rng.next_f64()))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
 }
 
-use std::time::{SystemTime, UNIX_EPOCH};
-pub struct Clock;
+pub struct RandomInt {
+    rng: Rc<Xorshift>,
+}
 
-impl LoxCallable for Clock {
+impl RandomInt {
+    pub fn new(rng: Rc<Xorshift>) -> Self {
+        RandomInt { rng }
+    }
+}
+
+impl LoxCallable for RandomInt {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let lo = integer_arg(&arguments[0], paren)?;
+        let hi = integer_arg(&arguments[1], paren)?;
+        if lo >= hi {
+            return Err(native_error(
+                paren,
+                "Lower bound must be less than upper bound.",
+            ));
+        }
+        // The span of a wide range such as [-9e18, 9e18) doesn't fit in an
+        // i64, but it always fits in a u64, and lo plus the offset is below
+        // hi, so wrapping arithmetic gives the exact result.
+        let span = (hi as i128 - lo as i128) as u64;
+        let offset = self.rng.next_u64() % span;
+        Ok(Value::Number(lo.wrapping_add(offset as i64) as f64))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+pub struct Seed {
+    rng: Rc<Xorshift>,
+}
+
+impl Seed {
+    pub fn new(rng: Rc<Xorshift>) -> Self {
+        Seed { rng }
+    }
+}
+
+impl LoxCallable for Seed {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let seed = integer_arg(&arguments[0], paren)?;
+        self.rng.seed(seed as u64);
+        Ok(Value::Nil)
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+pub struct Min;
+
+impl LoxCallable for Min {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let a = number_arg(&arguments[0], paren)?;
+        let b = number_arg(&arguments[1], paren)?;
+        Ok(Value::Number(a.min(b)))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+pub struct Max;
+
+impl LoxCallable for Max {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let a = number_arg(&arguments[0], paren)?;
+        let b = number_arg(&arguments[1], paren)?;
+        Ok(Value::Number(a.max(b)))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+pub struct Clamp;
+
+impl LoxCallable for Clamp {
+    fn arity(&self) -> usize {
+        3
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let x = number_arg(&arguments[0], paren)?;
+        let lo = number_arg(&arguments[1], paren)?;
+        let hi = number_arg(&arguments[2], paren)?;
+        if lo > hi {
+            return Err(native_error(
+                paren,
+                "Lower bound must not exceed upper bound.",
+            ));
+        }
+        Ok(Value::Number(x.max(lo).min(hi)))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// `approxEq(a, b, eps)` is true when `a` and `b` differ by less than
+/// `eps`, for tests where `==`'s exact comparison is too strict.
+pub struct ApproxEq;
+
+impl LoxCallable for ApproxEq {
+    fn arity(&self) -> usize {
+        3
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let a = number_arg(&arguments[0], paren)?;
+        let b = number_arg(&arguments[1], paren)?;
+        let eps = number_arg(&arguments[2], paren)?;
+        if eps < 0.0 {
+            return Err(native_error(paren, "Tolerance must not be negative."));
+        }
+        Ok(Value::Boolean((a - b).abs() < eps))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// A one-argument `f64` function such as `sin` or `log`. A finite argument
+/// never leaks a non-finite number into the program: a NaN result, as from
+/// `log(-1)`, raises "Math domain error.", and an infinite one, as from
+/// `exp(1000)` or `log(0)`, raises "Math result out of range.".
+pub struct MathFunction {
+    function: fn(f64) -> f64,
+}
+
+impl MathFunction {
+    pub fn new(function: fn(f64) -> f64) -> Self {
+        MathFunction { function }
+    }
+}
+
+impl LoxCallable for MathFunction {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let x = number_arg(&arguments[0], paren)?;
+        let result = (self.function)(x);
+        if x.is_finite() && result.is_nan() {
+            return Err(native_error(paren, "Math domain error."));
+        }
+        if x.is_finite() && result.is_infinite() {
+            return Err(native_error(paren, "Math result out of range."));
+        }
+        Ok(Value::Number(result))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+pub struct Chars;
+
+impl LoxCallable for Chars {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let s = string_arg(&arguments[0], paren)?;
+        let chars = s.chars().map(|c| Value::String(c.to_string())).collect();
+        Ok(Value::List(Rc::new(RefCell::new(chars))))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+pub struct CharAt;
+
+impl LoxCallable for CharAt {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let s = string_arg(&arguments[0], paren)?;
+        let index = integer_arg(&arguments[1], paren)?;
+        match usize::try_from(index).ok().and_then(|i| s.chars().nth(i)) {
+            Some(c) => Ok(Value::String(c.to_string())),
+            None => Err(native_error(paren, "String index out of range.")),
+        }
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// `ord(s)` is the code point of the single character in `s`.
+pub struct Ord;
+
+impl LoxCallable for Ord {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let s = string_arg(&arguments[0], paren)?;
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(Value::Number(c as u32 as f64)),
+            _ => Err(native_error(paren, "Expected a single-character string.")),
+        }
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// `chr(n)` is the one-character string for code point `n`; the inverse
+/// of `ord`.
+pub struct Chr;
+
+impl LoxCallable for Chr {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let n = integer_arg(&arguments[0], paren)?;
+        match u32::try_from(n).ok().and_then(char::from_u32) {
+            Some(c) => Ok(Value::String(c.to_string())),
+            None => Err(native_error(
+                paren,
+                &format!("Invalid unicode code point: {}", n),
+            )),
+        }
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// `assertEq(actual, expected)` raises a runtime error unless the two values
+/// are equal under `==`.
+pub struct AssertEq;
+
+impl LoxCallable for AssertEq {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Evaluate,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let (actual, expected) = (&arguments[0], &arguments[1]);
+        if interpreter.is_equal(actual, expected) {
+            return Ok(Value::Nil);
+        }
+        Err(native_error(
+            paren,
+            &format!(
+                "Assertion failed: expected {} but got {}.",
+                expected, actual
+            ),
+        ))
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// `sideEffect(value)` records the value in a log and returns it, so a test
+/// can tell whether an operand was evaluated at all.
+pub struct SideEffect {
+    log: Rc<RefCell<Vec<Value>>>,
+}
+
+impl SideEffect {
+    pub fn new(log: Rc<RefCell<Vec<Value>>>) -> Self {
+        SideEffect { log }
+    }
+}
+
+impl LoxCallable for SideEffect {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Evaluate,
+        _paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        self.log.borrow_mut().push(arguments[0].clone());
+        Ok(arguments[0].clone())
+    }
+
+    fn to_string(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// `getLog()` returns a new list of every value passed to `sideEffect` so
+/// far, oldest first.
+pub struct GetLog {
+    log: Rc<RefCell<Vec<Value>>>,
+}
+
+impl GetLog {
+    pub fn new(log: Rc<RefCell<Vec<Value>>>) -> Self {
+        GetLog { log }
+    }
+}
+
+impl LoxCallable for GetLog {
     fn arity(&self) -> usize {
         0
     }
@@ -27,11 +1324,11 @@ impl LoxCallable for Clock {
     fn call(
         &self,
         _interpreter: &mut Evaluate,
+        _paren: &Token,
         _arguments: Vec<Value>,
     ) -> Result<Value, RuntimeError> {
-        let start = SystemTime::now();
-        let since_epoch = start.duration_since(UNIX_EPOCH).unwrap();
-        Ok(Value::Number((since_epoch.as_millis() / 1000) as f64))
+        let log = self.log.borrow().clone();
+        Ok(Value::List(Rc::new(RefCell::new(log))))
     }
 
     fn to_string(&self) -> String {
@@ -43,7 +1340,11 @@ pub struct LoxFunction {
     name: Token,
     parameter: Vec<Token>,
     body: Vec<Stmt>,
+    return_type: Option<Token>,
     closure: Rc<RefCell<Environment>>,
+    // The globals the function was defined under; a module's top-level
+    // scope for functions declared in an imported module.
+    globals: Rc<RefCell<Environment>>,
 }
 
 impl LoxFunction {
@@ -51,13 +1352,17 @@ impl LoxFunction {
         name: Token,
         parameter: Vec<Token>,
         body: Vec<Stmt>,
+        return_type: Option<Token>,
         closure: Rc<RefCell<Environment>>,
+        globals: Rc<RefCell<Environment>>,
     ) -> Self {
         LoxFunction {
             name,
             parameter,
             body,
+            return_type,
             closure,
+            globals,
         }
     }
 }
@@ -70,6 +1375,7 @@ impl LoxCallable for LoxFunction {
     fn call(
         &self,
         interpreter: &mut Evaluate,
+        _paren: &Token,
         arguments: Vec<Value>,
     ) -> Result<Value, RuntimeError> {
         let mut env = Environment::from_enclosing(Rc::clone(&self.closure));
@@ -78,14 +1384,37 @@ impl LoxCallable for LoxFunction {
             env.define(param.lexeme.clone(), arg);
         }
 
-        match interpreter.execute_block(self.body.clone(), Rc::clone(&Rc::new(RefCell::new(env)))) {
-            Ok(_) => Ok(Value::Nil),
-            Err(RuntimeError::Return(ret)) => Ok(ret.value),
-            Err(err) => Err(err),
+        let globals = std::mem::replace(&mut interpreter.globals, Rc::clone(&self.globals));
+        let result =
+            interpreter.execute_block(self.body.clone(), Rc::clone(&Rc::new(RefCell::new(env))));
+        interpreter.globals = globals;
+        let value = match result {
+            Ok(_) => Value::Nil,
+            Err(RuntimeError::Return(ret)) => ret.value,
+            Err(err) => return Err(err),
+        };
+        // Only annotated functions are checked; the annotation names one of
+        // the `Value::type_name` values.
+        match &self.return_type {
+            Some(expected) if expected.lexeme != value.type_name() => Err(RuntimeError::Error {
+                message: format!(
+                    "Function '{}' must return {} but returned {}.",
+                    self.name.lexeme,
+                    expected.lexeme,
+                    value.type_name()
+                ),
+                line: expected.line,
+                token: expected.clone(),
+            }),
+            _ => Ok(value),
         }
     }
 
     fn to_string(&self) -> String {
         format!("<fn {}>", self.name.lexeme)
     }
+
+    fn name(&self) -> Option<String> {
+        Some(self.name.lexeme.clone())
+    }
 }
@@ -1,11 +1,11 @@
+use crate::errors;
 use std::fs;
-use std::io::{self, Write};
 use std::fmt;
 use std::iter::Peekable;
+use std::path::Path;
 use std::str::Chars;
 #[derive(Debug, Clone, PartialEq)]
 #[allow(non_camel_case_types)]
-
 pub enum TokenType {
     // Single-character tokens.
     LEFT_PAREN,
@@ -13,6 +13,7 @@ pub enum TokenType {
     LEFT_BRACE,
     RIGHT_BRACE,
     COMMA,
+    COLON,
     DOT,
     MINUS,
     PLUS,
@@ -29,6 +30,9 @@ pub enum TokenType {
     GREATER_EQUAL,
     LESS,
     LESS_EQUAL,
+    QUESTION_QUESTION,
+    DOT_DOT,
+    DOT_DOT_EQUAL,
 
     // Literals.
     IDENTIFIER,
@@ -37,17 +41,29 @@ pub enum TokenType {
 
     // Keywords.
     AND,
+    AS,
+    BREAK,
+    CASE,
     CLASS,
+    CONTINUE,
+    DEFAULT,
+    DIV,
+    DO,
+    ELIF,
     ELSE,
+    EXPORT,
     FALSE,
     FUN,
     FOR,
     IF,
+    IMPORT,
+    IN,
     NIL,
     OR,
     PRINT,
     RETURN,
     SUPER,
+    SWITCH,
     THIS,
     TRUE,
     VAR,
@@ -59,17 +75,29 @@ pub enum TokenType {
 fn keywords(key: &str) -> Option<TokenType> {
     match key {
         "and" => Some(TokenType::AND),
+        "as" => Some(TokenType::AS),
+        "break" => Some(TokenType::BREAK),
+        "case" => Some(TokenType::CASE),
         "class" => Some(TokenType::CLASS),
+        "continue" => Some(TokenType::CONTINUE),
+        "default" => Some(TokenType::DEFAULT),
+        "div" => Some(TokenType::DIV),
+        "do" => Some(TokenType::DO),
+        "elif" => Some(TokenType::ELIF),
         "else" => Some(TokenType::ELSE),
+        "export" => Some(TokenType::EXPORT),
         "false" => Some(TokenType::FALSE),
         "for" => Some(TokenType::FOR),
         "fun" => Some(TokenType::FUN),
         "if" => Some(TokenType::IF),
+        "import" => Some(TokenType::IMPORT),
+        "in" => Some(TokenType::IN),
         "nil" => Some(TokenType::NIL),
         "or" => Some(TokenType::OR),
         "print" => Some(TokenType::PRINT),
         "return" => Some(TokenType::RETURN),
         "super" => Some(TokenType::SUPER),
+        "switch" => Some(TokenType::SWITCH),
         "this" => Some(TokenType::THIS),
         "true" => Some(TokenType::TRUE),
         "var" => Some(TokenType::VAR),
@@ -104,17 +132,53 @@ pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub line: usize,
+    /// 1-based column of the token's first character, counted in chars;
+    /// 0 for tokens the parser makes up.
+    pub column: usize,
     pub literal: Literal,
 }
 
-fn to_string(token: Token) -> String {
+pub fn to_string(token: Token) -> String {
     format!("{:?} {} {}", token.token_type, token.lexeme, token.literal)
 }
 
+// The characters being scanned, keeping count of the column of the last
+// one taken.
+#[derive(Clone)]
+struct Cursor<'a> {
+    chars: Peekable<Chars<'a>>,
+    column: usize,
+}
+
+impl Cursor<'_> {
+    fn peek(&mut self) -> Option<&char> {
+        self.chars.peek()
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let ch = self.chars.next()?;
+        if ch == '\n' {
+            self.column = 0;
+        } else {
+            self.column += 1;
+        }
+        Some(ch)
+    }
+}
+
 struct Lexer {
     tokens: Vec<Token>,
     had_error: bool,
     line: usize,
+    // The column of the first character of the token being scanned.
+    column: usize,
+    // When set, errors are collected in `errors` instead of printed.
+    silent: bool,
+    errors: Vec<(usize, usize, String)>,
 }
 
 impl Lexer {
@@ -123,15 +187,18 @@ impl Lexer {
             tokens: Vec::new(),
             had_error: false,
             line: 1,
+            column: 0,
+            silent: false,
+            errors: Vec::new(),
         }
     }
 
     pub fn error(&mut self, line: usize, message: &str) {
-        self.report(line, "", message);
-    }
-
-    pub fn report(&mut self, line: usize, location: &str, message: &str) {
-        eprintln!("[line {}] Error{}: {}", line, location, message);
+        if self.silent {
+            self.errors.push((line, self.column, message.to_string()));
+        } else {
+            errors::report_lex(line, self.column, message);
+        }
         self.had_error = true;
     }
 
@@ -144,13 +211,14 @@ impl Lexer {
             token_type,
             lexeme: current,
             line: self.line,
+            column: self.column,
             literal,
         })
     }
 
     fn match_next(
         &mut self,
-        chars: &mut Peekable<Chars>,
+        chars: &mut Cursor,
         current: char,
         expected: char,
         double_type: TokenType,
@@ -165,9 +233,9 @@ impl Lexer {
         self.add_token(token_type, lexeme);
     }
 
-    fn handle_slash(&mut self, chars: &mut Peekable<Chars>) {
+    fn handle_slash(&mut self, chars: &mut Cursor) {
         if let Some(&'/') = chars.peek() {
-            while chars.peek().map_or(false, |&c| c != '\n') {
+            while chars.peek().is_some_and(|&c| c != '\n') {
                 chars.next();
             }
         } else {
@@ -175,38 +243,83 @@ impl Lexer {
         }
     }
 
-    fn scan_string(&mut self, chars: &mut Peekable<Chars>) {
+    fn scan_string(&mut self, chars: &mut Cursor) {
         let mut value = String::new();
-        while chars.peek().is_some() {
-            if let Some(ch) = chars.peek() {
-                match ch {
-                    '"' => {
-                        chars.next();
-                        return self.add_token_literal(TokenType::STRING, format!("\"{}\"", value), Literal::String(value.clone()));
-                    }
-                    '\n' => {
-                        self.line += 1;
-                        value.push(*ch);
+        let mut lexeme = String::from("\"");
+        while let Some(ch) = chars.next() {
+            lexeme.push(ch);
+            match ch {
+                '"' => {
+                    return self.add_token_literal(TokenType::STRING, lexeme, Literal::String(value));
+                }
+                '\\' if chars.peek() == Some(&'u') => {
+                    chars.next();
+                    lexeme.push('u');
+                    if let Some(escaped) = self.scan_unicode_escape(chars, &mut lexeme) {
+                        value.push(escaped);
                     }
-                    _ => value.push(*ch),
                 }
+                '\n' => {
+                    self.line += 1;
+                    value.push(ch);
+                }
+                _ => value.push(ch),
             }
-            chars.next();
         }
         self.error(self.line, "Unterminated string.");
     }
 
-    fn scan_num(&mut self, chars: &mut Peekable<Chars>, cur: char) {
+    // Reads the `{1F600}` part of a `\u{1F600}` escape.
+    fn scan_unicode_escape(&mut self, chars: &mut Cursor, lexeme: &mut String) -> Option<char> {
+        if chars.peek() != Some(&'{') {
+            self.error(self.line, "Invalid unicode escape: expect '{' after '\\u'.");
+            return None;
+        }
+        lexeme.push('{');
+        chars.next();
+        let mut digits = String::new();
+        while let Some(&ch) = chars.peek() {
+            if ch == '}' || ch == '"' || ch == '\n' {
+                break;
+            }
+            digits.push(ch);
+            lexeme.push(ch);
+            chars.next();
+        }
+        if chars.peek() != Some(&'}') {
+            self.error(self.line, "Invalid unicode escape: expect '}' after code point.");
+            return None;
+        }
+        lexeme.push('}');
+        chars.next();
+
+        let valid = !digits.is_empty() && digits.len() <= 6 && digits.chars().all(|c| c.is_ascii_hexdigit());
+        match u32::from_str_radix(&digits, 16).ok().filter(|_| valid).and_then(char::from_u32) {
+            Some(escaped) => Some(escaped),
+            None => {
+                self.error(self.line, &format!("Invalid unicode code point: {}", digits));
+                None
+            }
+        }
+    }
+
+    fn scan_num(&mut self, chars: &mut Cursor, cur: char) {
         let mut value = String::from(cur);
         while chars.peek().is_some() {
-            if let Some(digit) = chars.peek() {
+            if let Some(&digit) = chars.peek() {
                 match digit {
-                    '0'..='9' => value.push(*digit),
-                    '.' => value.push(*digit),
+                    '0'..='9' => value.push(digit),
+                    // A '.' only belongs to the number when a digit follows
+                    // it, so `0..10` scans as NUMBER DOT_DOT NUMBER.
+                    '.' if !value.contains('.')
+                        && chars.clone().nth(1).is_some_and(|c| c.is_ascii_digit()) =>
+                    {
+                        value.push(digit)
+                    }
                     _ => break,
                 }
             }
-            chars.next(); 
+            chars.next();
         }
         let num = value.parse::<f64>().unwrap();
         self.add_token_literal(TokenType::NUMBER, value, Literal::Number(num));
@@ -214,7 +327,7 @@ impl Lexer {
 
     fn scan_identifier(
         &mut self,
-        chars: &mut Peekable<Chars>,
+        chars: &mut Cursor,
         cur: char,
     ) {
         let mut identifier = String::from(cur);
@@ -234,24 +347,42 @@ impl Lexer {
     }
 
     pub fn scan_token(&mut self, source: &str) {
-        let mut chars = source.chars().peekable();
+        let mut chars = Cursor {
+            chars: source.chars().peekable(),
+            column: 0,
+        };
 
         while let Some(current) = chars.next() {
+            self.column = chars.column;
             match current {
                 '(' => self.add_token(TokenType::LEFT_PAREN, current.to_string()),
                 ')' => self.add_token(TokenType::RIGHT_PAREN, current.to_string()),
                 '{' => self.add_token(TokenType::LEFT_BRACE, current.to_string()),
                 '}' => self.add_token(TokenType::RIGHT_BRACE, current.to_string()),
                 ',' => self.add_token(TokenType::COMMA, current.to_string()),
+                '.' if chars.peek() == Some(&'.') => {
+                    chars.next();
+                    if chars.peek() == Some(&'=') {
+                        chars.next();
+                        self.add_token(TokenType::DOT_DOT_EQUAL, "..=".to_string())
+                    } else {
+                        self.add_token(TokenType::DOT_DOT, "..".to_string())
+                    }
+                }
                 '.' => self.add_token(TokenType::DOT, current.to_string()),
                 '-' => self.add_token(TokenType::MINUS, current.to_string()),
                 '+' => self.add_token(TokenType::PLUS, current.to_string()),
                 ';' => self.add_token(TokenType::SEMICOLON, current.to_string()),
+                ':' => self.add_token(TokenType::COLON, current.to_string()),
                 '*' => self.add_token(TokenType::STAR, current.to_string()),
                 '!' => self.match_next(&mut chars, current, '=', TokenType::BANG_EQUAL, TokenType::BANG),
                 '=' => self.match_next(&mut chars, current, '=', TokenType::EQUAL_EQUAL, TokenType::EQUAL),
                 '<' => self.match_next(&mut chars, current, '=', TokenType::LESS_EQUAL, TokenType::LESS),
                 '>' => self.match_next(&mut chars, current, '=', TokenType::GREATER_EQUAL, TokenType::GREATER),
+                '?' if chars.peek() == Some(&'?') => {
+                    chars.next();
+                    self.add_token(TokenType::QUESTION_QUESTION, "??".to_string())
+                }
                 '/' => self.handle_slash(&mut chars),
                 '"' => self.scan_string(&mut chars),
                 '0'..='9' => self.scan_num(&mut chars, current),
@@ -268,17 +399,12 @@ impl Lexer {
                 }
             }
         }
+        self.column = chars.column + 1;
         self.add_token(TokenType::EOF, "".to_string());
     }
 
     pub fn lex(&mut self, filename: &str) {
-        let file_contents = match fs::read_to_string(filename) {
-            Ok(contents) => contents,
-            Err(_) => {
-                writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
-                return;
-            }
-        };
+        let file_contents = load_source(filename);
     
         if file_contents.is_empty() {
             println!("EOF  null");
@@ -290,22 +416,88 @@ impl Lexer {
         }
 
         if self.had_error {
-            std::process::exit(65)
+            errors::exit(errors::EX_DATAERR)
         } 
-        std::process::exit(0)
+        errors::exit(errors::EXIT_SUCCESS)
+    }
+}
+
+pub enum SourceError {
+    Unreadable,
+    /// The file's bytes stop being valid UTF-8 at this offset.
+    InvalidUtf8(usize),
+}
+
+pub fn read_source(path: &Path) -> Result<String, SourceError> {
+    let bytes = fs::read(path).map_err(|_| SourceError::Unreadable)?;
+    String::from_utf8(bytes)
+        .map_err(|error| SourceError::InvalidUtf8(error.utf8_error().valid_up_to()))
+}
+
+// Reads the script named on the command line, exiting with a message if it
+// can't be read (EX_NOINPUT) or isn't UTF-8 (EX_DATAERR).
+pub fn load_source(filename: &str) -> String {
+    match read_source(Path::new(filename)) {
+        Ok(contents) => contents,
+        Err(SourceError::Unreadable) => {
+            errors::report_file(&format!("Failed to read file {}.", filename));
+            errors::exit(errors::EX_NOINPUT)
+        }
+        Err(SourceError::InvalidUtf8(byte)) => {
+            errors::report_file(&format!("File {} is not valid UTF-8 at byte {}.", filename, byte));
+            errors::exit(errors::EX_DATAERR)
+        }
     }
 }
 
 pub fn return_tokens(source: &str) -> Vec<Token> {
+    let (tokens, had_error) = scan_tokens(source);
+    if had_error {
+        errors::exit(errors::EX_DATAERR)
+    }
+    tokens
+}
+
+pub fn scan_tokens(source: &str) -> (Vec<Token>, bool) {
     let mut lexer = Lexer::new();
     lexer.scan_token(source);
-    if lexer.had_error {
-        std::process::exit(65)
-    }
-    lexer.tokens
+    (lexer.tokens, lexer.had_error)
+}
+
+/// Like `scan_tokens`, but returns the errors as `(line, column, message)`
+/// triples instead of printing them.
+pub fn scan_tokens_silently(source: &str) -> (Vec<Token>, Vec<(usize, usize, String)>) {
+    let mut lexer = Lexer::new();
+    lexer.silent = true;
+    lexer.scan_token(source);
+    (lexer.tokens, lexer.errors)
 }
 
 pub fn run_lexer(filename: &str) {
     let mut lexer = Lexer::new();
     lexer.lex(filename);
 }
+
+// Prints how many tokens of each type the file has, most frequent first,
+// e.g. `IDENTIFIER: 42, NUMBER: 10`.
+pub fn run_token_count(filename: &str) {
+    let file_contents = load_source(filename);
+
+    let mut counts: Vec<(TokenType, usize)> = Vec::new();
+    for token in return_tokens(&file_contents) {
+        if token.token_type == TokenType::EOF {
+            continue;
+        }
+        match counts.iter_mut().find(|(token_type, _)| *token_type == token.token_type) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((token.token_type, 1)),
+        }
+    }
+    counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+    let summary: Vec<String> = counts
+        .iter()
+        .map(|(token_type, count)| format!("{:?}: {}", token_type, count))
+        .collect();
+    println!("{}", summary.join(", "));
+}
@@ -1,6 +1,9 @@
+pub mod color;
+pub mod errors;
 pub mod lexer;
 pub mod parse;
 pub mod evaluate;
 pub mod environment;
 pub mod function;
 pub mod resolver;
+pub mod run;
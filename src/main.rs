@@ -1,28 +1,102 @@
 use std::env;
-use std::io::{self, Write};
 use std::process::exit;
+use std::thread;
 pub mod evaluate;
+pub mod color;
+pub mod errors;
 pub mod lexer;
 pub mod parse;
 pub mod function;
 pub mod environment;
 pub mod resolver;
+pub mod run;
+
+const INTERPRETER_STACK_SIZE: usize = 64 * 1024 * 1024;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    // Anything after `--` belongs to the script, not the interpreter.
+    let mut args: Vec<String> = env::args().collect();
+    let script_args = match args.iter().position(|arg| arg == "--") {
+        Some(index) => args.split_off(index)[1..].to_vec(),
+        None => Vec::new(),
+    };
+    let (flags, args): (Vec<String>, Vec<String>) =
+        args.into_iter().partition(|arg| arg.starts_with("--"));
+    if flags.iter().any(|flag| flag == "--no-color") {
+        color::disable();
+    }
+    if flags.iter().any(|flag| flag == "--backtrace") {
+        errors::enable_backtrace();
+    }
+    if flags.iter().any(|flag| flag == "--json-errors") {
+        errors::enable_json_errors();
+    }
     if args.len() < 3 {
-        writeln!(io::stderr(), "Usage: {} command <filename>", args[0]).unwrap();
-        exit(1);
+        eprintln!(
+            "Usage: {} command [--no-color] [--backtrace] [--json-errors] <filename> [-- args...]",
+            args[0]
+        );
+        exit(errors::EXIT_FAILURE);
+    }
+
+    // Each Lox call nests several Rust frames, so the interpreter runs on a
+    // thread with room for `MAX_CALL_DEPTH` calls.
+    let interpreter = thread::Builder::new()
+        .stack_size(INTERPRETER_STACK_SIZE)
+        .spawn(move || dispatch(&args, &flags, script_args))
+        .expect("failed to spawn interpreter thread");
+    if interpreter.join().is_err() {
+        errors::exit(errors::EX_SOFTWARE);
     }
+    errors::exit(errors::EXIT_SUCCESS);
+}
+
+fn dispatch(args: &[String], flags: &[String], script_args: Vec<String>) {
+    let has_flag = |name: &str| flags.iter().any(|flag| flag == name);
+    // The value of a `--name=value` flag.
+    let flag_value = |name: &str| {
+        flags
+            .iter()
+            .find_map(|flag| flag.strip_prefix(name)?.strip_prefix('='))
+    };
 
     match args[1].as_str() {
+        "tokenize" if has_flag("--count") => lexer::run_token_count(&args[2]),
         "tokenize" => lexer::run_lexer(&args[2]),
-        "parse" => parse::run_parser(&args[2]),
-        "evaluate" => evaluate::evaluate(&args[2], true),
-        "run" => evaluate::evaluate(&args[2], false),
+        "parse" => {
+            let output = if has_flag("--quiet") || has_flag("--ast-only") {
+                parse::ParseOutput::Quiet
+            } else if has_flag("--tree") {
+                parse::ParseOutput::Tree
+            } else {
+                parse::ParseOutput::Ast
+            };
+            parse::run_parser(&args[2], output)
+        }
+        "evaluate" => run::run_file(&args[2], true, run::RunOptions::default()),
+        "run" => {
+            let options = run::RunOptions {
+                trace: has_flag("--trace"),
+                lenient: has_flag("--lenient"),
+                sandbox: has_flag("--sandbox"),
+                strict: has_flag("--strict"),
+                args: script_args,
+                max_iterations: flag_value("--max-iterations").map(|value| {
+                    value.parse().unwrap_or_else(|_| {
+                        eprintln!("Invalid value for --max-iterations: {}", value);
+                        exit(errors::EXIT_FAILURE);
+                    })
+                }),
+            };
+            run::run_file(&args[2], false, options)
+        }
+        "eval-expr" => run::run_expression(&args[2]),
+        "debug" => run::run_debug(&args[2]),
+        "check" => resolver::run_check(&args[2], has_flag("--warn-unused")),
+        "resolve-dump" => resolver::run_resolve_dump(&args[2]),
         cmd => {
-            writeln!(io::stderr(), "Unknown command: {}", cmd).unwrap();
-            exit(1);
+            eprintln!("Unknown command: {}", cmd);
+            exit(errors::EXIT_FAILURE);
         }
     }
 }
@@ -1,10 +1,20 @@
-use crate::lexer::{return_tokens, Literal, Token, TokenType};
-use std::fs;
-use std::io::{self, Write};
+use crate::errors;
+use crate::lexer::{load_source, return_tokens, scan_tokens_silently, Literal, Token, TokenType};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_EXPR_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Hands out ids that identify variable references for the resolver. They
+/// are unique across every parse in the process so ASTs can be cloned freely.
+fn next_expr_id() -> usize {
+    NEXT_EXPR_ID.fetch_add(1, Ordering::Relaxed)
+}
 
 #[derive(Debug, Clone)]
 pub enum Expr {
     Assign {
+        id: usize,
         name: Token,
         value: Box<Expr>,
     },
@@ -18,9 +28,21 @@ pub enum Expr {
         paren: Token,
         arguments: Vec<Expr>,
     },
+    Get {
+        object: Box<Expr>,
+        name: Token,
+    },
     Grouping {
         expression: Box<Expr>,
     },
+    /// `if (condition) a else b` in expression position; the `else` is
+    /// required so there is always a value.
+    If {
+        keyword: Token,
+        condition: Box<Expr>,
+        then_branch: Box<Expr>,
+        else_branch: Box<Expr>,
+    },
     Literal {
         value: Literal,
     },
@@ -34,12 +56,111 @@ pub enum Expr {
         right: Box<Expr>,
     },
     Variable {
+        id: usize,
         name: Token,
     },
     Null,
 }
 
 impl Expr {
+    /// The line an expression starts on, taken from its leftmost token.
+    /// Literals don't keep their token, so an expression made only of
+    /// literals has no line.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Expr::Assign { name, .. } | Expr::Variable { name, .. } => Some(name.line),
+            Expr::Binary { left, operator, .. } | Expr::Logical { left, operator, .. } => {
+                left.line().or(Some(operator.line))
+            }
+            Expr::Call { callee, paren, .. } => callee.line().or(Some(paren.line)),
+            Expr::Get { object, name } => object.line().or(Some(name.line)),
+            Expr::Grouping { expression } => expression.line(),
+            Expr::If { keyword, .. }
+            | Expr::Unary {
+                operator: keyword, ..
+            } => Some(keyword.line),
+            Expr::Literal { .. } | Expr::Null => None,
+        }
+    }
+
+    /// Renders the expression as an indented tree, one node per line.
+    pub fn tree_print(&self, indent: usize) -> String {
+        match self {
+            Expr::Assign { name, value, .. } => {
+                tree_line(indent, &format!("Assign {}", name.lexeme), Some(name.line))
+                    + &value.tree_print(indent + 1)
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                tree_line(
+                    indent,
+                    &format!("Binary {}", operator.lexeme),
+                    Some(operator.line),
+                ) + &left.tree_print(indent + 1)
+                    + &right.tree_print(indent + 1)
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                let mut out = tree_line(indent, "Call", self.line());
+                out += &callee.tree_print(indent + 1);
+                for argument in arguments {
+                    out += &argument.tree_print(indent + 1);
+                }
+                out
+            }
+            Expr::Get { object, name } => {
+                tree_line(indent, &format!("Get {}", name.lexeme), Some(name.line))
+                    + &object.tree_print(indent + 1)
+            }
+            Expr::Grouping { expression } => {
+                tree_line(indent, "Grouping", self.line()) + &expression.tree_print(indent + 1)
+            }
+            Expr::If {
+                keyword,
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                tree_line(indent, "If", Some(keyword.line))
+                    + &condition.tree_print(indent + 1)
+                    + &then_branch.tree_print(indent + 1)
+                    + &else_branch.tree_print(indent + 1)
+            }
+            Expr::Literal { .. } => {
+                tree_line(indent, &format!("Literal {}", self.ast_print()), None)
+            }
+            Expr::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                tree_line(
+                    indent,
+                    &format!("Logical {}", operator.lexeme),
+                    Some(operator.line),
+                ) + &left.tree_print(indent + 1)
+                    + &right.tree_print(indent + 1)
+            }
+            Expr::Unary { operator, right } => {
+                tree_line(
+                    indent,
+                    &format!("Unary {}", operator.lexeme),
+                    Some(operator.line),
+                ) + &right.tree_print(indent + 1)
+            }
+            Expr::Variable { name, .. } => tree_line(
+                indent,
+                &format!("Variable {}", name.lexeme),
+                Some(name.line),
+            ),
+            Expr::Null => tree_line(indent, "Null", None),
+        }
+    }
+
     fn ast_print(&self) -> String {
         match self {
             Expr::Binary {
@@ -55,6 +176,17 @@ impl Expr {
                 )
             }
             Expr::Grouping { expression } => format!("(group {})", expression.ast_print()),
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => format!(
+                "(if {} {} {})",
+                condition.ast_print(),
+                then_branch.ast_print(),
+                else_branch.ast_print()
+            ),
             Expr::Literal { value } => match value {
                 Literal::String(s) => s.clone(),
                 Literal::Number(n) => format!("{:?}", n),
@@ -65,8 +197,8 @@ impl Expr {
             Expr::Unary { operator, right } => {
                 format!("({} {})", operator.lexeme, right.ast_print())
             }
-            Expr::Variable { name } => format!("{}", name.lexeme),
-            Expr::Assign { name, value } => {
+            Expr::Variable { name, .. } => name.lexeme.to_string(),
+            Expr::Assign { name, value, .. } => {
                 format!("(= {} {})", name.lexeme, value.ast_print())
             }
             Expr::Logical {
@@ -81,36 +213,568 @@ impl Expr {
                     right.ast_print()
                 )
             }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                let mut out = format!("(call {}", callee.ast_print());
+                for argument in arguments {
+                    out += &format!(" {}", argument.ast_print());
+                }
+                out + ")"
+            }
+            Expr::Get { object, name } => format!("(. {} {})", object.ast_print(), name.lexeme),
             Expr::Null => "null".to_string(),
-            _ => String::new(),
         }
     }
 }
 
-#[derive(Debug)]
+// `(name head... stmt...)`, for statements that hold a list of statements.
+fn prefix_form(name: &str, head: &[String], statements: &[Stmt]) -> String {
+    let mut out = format!("({}", name);
+    for part in head {
+        out += &format!(" {}", part);
+    }
+    for statement in statements {
+        out += &format!(" {}", statement.ast_print());
+    }
+    out + ")"
+}
+
+fn tree_line(indent: usize, label: &str, line: Option<usize>) -> String {
+    match line {
+        Some(line) => format!("{}{} [line {}]\n", "  ".repeat(indent), label, line),
+        None => format!("{}{}\n", "  ".repeat(indent), label),
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ParseError {
     token: Token,
     message: String,
 }
 
+impl ParseError {
+    /// An error for a token that isn't the one the grammar wanted; the
+    /// message also names what was found, e.g. "Expect ')' after
+    /// arguments, found ';' (SEMICOLON).". At the end of input the report
+    /// already says "at end", so the message is left alone.
+    fn expected(token: Token, message: &str) -> Self {
+        let message = if token.token_type == TokenType::EOF {
+            message.to_string()
+        } else {
+            format!(
+                "{}, found '{}' ({:?}).",
+                message.trim_end_matches('.'),
+                token.lexeme,
+                token.token_type
+            )
+        };
+        ParseError { token, message }
+    }
+
+    pub fn line(&self) -> usize {
+        self.token.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.token.column
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+// Whether a range bound could be an integer. Only literals are known
+// before running, so any other expression is given the benefit of the
+// doubt.
+fn may_be_integer(bound: &Expr) -> bool {
+    match bound {
+        Expr::Literal {
+            value: Literal::Number(n),
+        } => n.fract() == 0.0,
+        Expr::Literal { .. } => false,
+        Expr::Unary { operator, right } if operator.token_type == TokenType::MINUS => {
+            may_be_integer(right)
+        }
+        Expr::Grouping { expression } => may_be_integer(expression),
+        _ => true,
+    }
+}
+
+// The names `Value::type_name` can return, usable as return annotations.
+const TYPE_NAMES: [&str; 7] = [
+    "boolean", "function", "list", "module", "nil", "number", "string",
+];
+
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
     pub had_error: bool,
     evaluate: bool,
-    error: i32,
+    // When set, errors are collected in `errors` instead of printed.
+    silent: bool,
+    errors: Vec<ParseError>,
+    /// Accept a print, var or expression statement that reaches the end of
+    /// the file without its `;`, with a warning. Set by `run` unless
+    /// `--strict` is given.
+    pub optional_final_semicolon: bool,
 }
 
 #[derive(Debug, Clone)]
 pub enum Stmt {
     Block(Vec<Stmt>),
+    Break(Token),
+    Continue(Token),
+    DoWhile(Token, Box<Stmt>, Expr),
+    Export(Token, Box<Stmt>),
     Expression(Expr),
-    Function(Token, Vec<Token>, Vec<Stmt>),
+    /// The optional last token is a `: type` return annotation.
+    Function(Token, Vec<Token>, Vec<Stmt>, Option<Token>),
     If(Expr, Box<Stmt>, Box<Option<Stmt>>),
-    Print(Expr),
+    Import(Token, String, Option<Token>),
+    Print(Vec<Expr>),
     Return(Token, Expr),
+    Switch(Expr, Vec<(Expr, Vec<Stmt>)>, Option<Vec<Stmt>>),
     Var(Token, Expr),
-    While(Expr, Box<Stmt>),
+    /// The token is the `while` or `for` keyword. The optional expression
+    /// is a desugared `for` loop's increment, which still runs after a
+    /// `continue`.
+    While(Token, Expr, Box<Stmt>, Option<Expr>),
+}
+
+impl Stmt {
+    /// Renders the statement in the same prefix form as `Expr::ast_print`.
+    /// An expression statement is just its expression, so `parse` output
+    /// for a lone expression is unchanged.
+    pub fn ast_print(&self) -> String {
+        match self {
+            Stmt::Block(statements) => prefix_form("block", &[], statements),
+            Stmt::Break(_) => "(break)".to_string(),
+            Stmt::Continue(_) => "(continue)".to_string(),
+            Stmt::DoWhile(_, body, condition) => {
+                format!("(do {} {})", body.ast_print(), condition.ast_print())
+            }
+            Stmt::Export(_, declaration) => format!("(export {})", declaration.ast_print()),
+            Stmt::Expression(expr) => expr.ast_print(),
+            Stmt::Function(name, params, body, _) => {
+                let params: Vec<&str> = params.iter().map(|p| p.lexeme.as_str()).collect();
+                let header = [name.lexeme.clone(), format!("({})", params.join(" "))];
+                prefix_form("fun", &header, body)
+            }
+            Stmt::If(condition, then_branch, else_branch) => match else_branch.as_ref() {
+                Some(else_branch) => format!(
+                    "(if {} {} {})",
+                    condition.ast_print(),
+                    then_branch.ast_print(),
+                    else_branch.ast_print()
+                ),
+                None => format!("(if {} {})", condition.ast_print(), then_branch.ast_print()),
+            },
+            Stmt::Import(_, path, alias) => match alias {
+                Some(alias) => format!("(import \"{}\" {})", path, alias.lexeme),
+                None => format!("(import \"{}\")", path),
+            },
+            Stmt::Print(exprs) => {
+                let exprs: Vec<String> = exprs.iter().map(|expr| expr.ast_print()).collect();
+                format!("(print {})", exprs.join(" "))
+            }
+            Stmt::Return(_, Expr::Null) => "(return)".to_string(),
+            Stmt::Return(_, value) => format!("(return {})", value.ast_print()),
+            Stmt::Switch(subject, cases, default) => {
+                let mut out = format!("(switch {}", subject.ast_print());
+                for (value, body) in cases {
+                    out += &format!(" {}", prefix_form("case", &[value.ast_print()], body));
+                }
+                if let Some(body) = default {
+                    out += &format!(" {}", prefix_form("default", &[], body));
+                }
+                out + ")"
+            }
+            Stmt::Var(name, Expr::Null) => format!("(var {})", name.lexeme),
+            Stmt::Var(name, initializer) => {
+                format!("(var {} {})", name.lexeme, initializer.ast_print())
+            }
+            Stmt::While(_, condition, body, None) => {
+                format!("(while {} {})", condition.ast_print(), body.ast_print())
+            }
+            Stmt::While(_, condition, body, Some(increment)) => format!(
+                "(for {} {} {})",
+                condition.ast_print(),
+                increment.ast_print(),
+                body.ast_print()
+            ),
+        }
+    }
+
+    /// The line the statement starts on, if any of its tokens record one.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Stmt::Block(statements) => statements.iter().find_map(|stmt| stmt.line()),
+            Stmt::Break(keyword)
+            | Stmt::Continue(keyword)
+            | Stmt::Export(keyword, _)
+            | Stmt::Import(keyword, _, _)
+            | Stmt::Return(keyword, _)
+            | Stmt::DoWhile(keyword, _, _)
+            | Stmt::While(keyword, _, _, _) => Some(keyword.line),
+            Stmt::Expression(expr) => expr.line(),
+            Stmt::Function(name, ..) | Stmt::Var(name, _) => Some(name.line),
+            Stmt::If(condition, _, _) | Stmt::Switch(condition, _, _) => condition.line(),
+            Stmt::Print(exprs) => exprs.iter().find_map(|expr| expr.line()),
+        }
+    }
+
+    /// Renders the statement and everything nested in it as an indented
+    /// tree, one node per line.
+    pub fn tree_print(&self, indent: usize) -> String {
+        match self {
+            Stmt::Block(statements) => {
+                let mut out = tree_line(indent, "Block", None);
+                for statement in statements {
+                    out += &statement.tree_print(indent + 1);
+                }
+                out
+            }
+            Stmt::Break(keyword) => tree_line(indent, "Break", Some(keyword.line)),
+            Stmt::Continue(keyword) => tree_line(indent, "Continue", Some(keyword.line)),
+            Stmt::DoWhile(keyword, body, condition) => {
+                tree_line(indent, "DoWhile", Some(keyword.line))
+                    + &body.tree_print(indent + 1)
+                    + &condition.tree_print(indent + 1)
+            }
+            Stmt::Export(keyword, declaration) => {
+                tree_line(indent, "Export", Some(keyword.line))
+                    + &declaration.tree_print(indent + 1)
+            }
+            Stmt::Expression(expr) => {
+                tree_line(indent, "Expression", expr.line()) + &expr.tree_print(indent + 1)
+            }
+            Stmt::Function(name, params, body, return_type) => {
+                let params: Vec<&str> = params.iter().map(|p| p.lexeme.as_str()).collect();
+                let return_type = match return_type {
+                    Some(return_type) => format!(" : {}", return_type.lexeme),
+                    None => String::new(),
+                };
+                let mut out = tree_line(
+                    indent,
+                    &format!(
+                        "Function {}({}){}",
+                        name.lexeme,
+                        params.join(", "),
+                        return_type
+                    ),
+                    Some(name.line),
+                );
+                for statement in body {
+                    out += &statement.tree_print(indent + 1);
+                }
+                out
+            }
+            Stmt::If(condition, then_branch, else_branch) => {
+                let mut out = tree_line(indent, "If", condition.line());
+                out += &condition.tree_print(indent + 1);
+                out += &tree_line(indent + 1, "Then", None);
+                out += &then_branch.tree_print(indent + 2);
+                if let Some(else_branch) = else_branch.as_ref() {
+                    out += &tree_line(indent + 1, "Else", None);
+                    out += &else_branch.tree_print(indent + 2);
+                }
+                out
+            }
+            Stmt::Import(keyword, path, alias) => {
+                let label = match alias {
+                    Some(alias) => format!("Import \"{}\" as {}", path, alias.lexeme),
+                    None => format!("Import \"{}\"", path),
+                };
+                tree_line(indent, &label, Some(keyword.line))
+            }
+            Stmt::Print(exprs) => {
+                let mut out = tree_line(indent, "Print", exprs[0].line());
+                for expr in exprs {
+                    out += &expr.tree_print(indent + 1);
+                }
+                out
+            }
+            Stmt::Return(keyword, value) => {
+                let mut out = tree_line(indent, "Return", Some(keyword.line));
+                if !matches!(value, Expr::Null) {
+                    out += &value.tree_print(indent + 1);
+                }
+                out
+            }
+            Stmt::Switch(subject, cases, default) => {
+                let mut out = tree_line(indent, "Switch", subject.line());
+                out += &subject.tree_print(indent + 1);
+                for (value, body) in cases {
+                    out += &tree_line(indent + 1, "Case", value.line());
+                    out += &value.tree_print(indent + 2);
+                    for statement in body {
+                        out += &statement.tree_print(indent + 2);
+                    }
+                }
+                if let Some(body) = default {
+                    out += &tree_line(indent + 1, "Default", None);
+                    for statement in body {
+                        out += &statement.tree_print(indent + 2);
+                    }
+                }
+                out
+            }
+            Stmt::Var(name, initializer) => {
+                let mut out = tree_line(indent, &format!("Var {}", name.lexeme), Some(name.line));
+                if !matches!(initializer, Expr::Null) {
+                    out += &initializer.tree_print(indent + 1);
+                }
+                out
+            }
+            Stmt::While(keyword, condition, body, increment) => {
+                let mut out = tree_line(indent, "While", Some(keyword.line))
+                    + &condition.tree_print(indent + 1)
+                    + &body.tree_print(indent + 1);
+                if let Some(increment) = increment {
+                    out += &tree_line(indent + 1, "Increment", None);
+                    out += &increment.tree_print(indent + 2);
+                }
+                out
+            }
+        }
+    }
+}
+
+// Binding strength of each expression form, loosest first, mirroring the
+// grammar. The source printer parenthesizes a subexpression only when it
+// binds more loosely than its position requires.
+const PREC_ASSIGNMENT: u8 = 1;
+const PREC_COALESCE: u8 = 2;
+const PREC_OR: u8 = 3;
+const PREC_AND: u8 = 4;
+const PREC_EQUALITY: u8 = 5;
+const PREC_COMPARISON: u8 = 6;
+const PREC_TERM: u8 = 7;
+const PREC_FACTOR: u8 = 8;
+const PREC_UNARY: u8 = 9;
+const PREC_CALL: u8 = 10;
+const PREC_PRIMARY: u8 = 11;
+
+fn operator_precedence(operator: &Token) -> u8 {
+    match operator.token_type {
+        TokenType::QUESTION_QUESTION => PREC_COALESCE,
+        TokenType::OR => PREC_OR,
+        TokenType::AND => PREC_AND,
+        TokenType::BANG_EQUAL | TokenType::EQUAL_EQUAL => PREC_EQUALITY,
+        TokenType::GREATER | TokenType::GREATER_EQUAL | TokenType::LESS | TokenType::LESS_EQUAL => {
+            PREC_COMPARISON
+        }
+        TokenType::MINUS | TokenType::PLUS => PREC_TERM,
+        _ => PREC_FACTOR,
+    }
+}
+
+impl Expr {
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Assign { .. } | Expr::If { .. } => PREC_ASSIGNMENT,
+            Expr::Binary { operator, .. } | Expr::Logical { operator, .. } => {
+                operator_precedence(operator)
+            }
+            Expr::Unary { .. } => PREC_UNARY,
+            Expr::Call { .. } | Expr::Get { .. } => PREC_CALL,
+            _ => PREC_PRIMARY,
+        }
+    }
+
+    /// Renders the expression as Lox source, in parentheses if it binds more
+    /// loosely than `min_precedence`.
+    fn source(&self, min_precedence: u8) -> String {
+        let text = match self {
+            Expr::Assign { name, value, .. } => {
+                format!("{} = {}", name.lexeme, value.source(PREC_ASSIGNMENT))
+            }
+            // Every binary operator is left-associative.
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            }
+            | Expr::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                let precedence = operator_precedence(operator);
+                format!(
+                    "{} {} {}",
+                    left.source(precedence),
+                    operator.lexeme,
+                    right.source(precedence + 1)
+                )
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                let arguments: Vec<String> = arguments
+                    .iter()
+                    .map(|argument| argument.source(PREC_ASSIGNMENT))
+                    .collect();
+                format!("{}({})", callee.source(PREC_CALL), arguments.join(", "))
+            }
+            Expr::Get { object, name } => format!("{}.{}", object.source(PREC_CALL), name.lexeme),
+            Expr::Grouping { expression } => format!("({})", expression.source(PREC_ASSIGNMENT)),
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => format!(
+                "if ({}) {} else {}",
+                condition.source(PREC_ASSIGNMENT),
+                then_branch.source(PREC_ASSIGNMENT),
+                else_branch.source(PREC_ASSIGNMENT)
+            ),
+            Expr::Literal { value } => match value {
+                Literal::String(s) => quote_string(s),
+                Literal::Number(n) => n.to_string(),
+                Literal::Boolean(b) => b.to_string(),
+                Literal::Identifier(name) => name.clone(),
+                Literal::None => "nil".to_string(),
+            },
+            Expr::Unary { operator, right } => {
+                format!("{}{}", operator.lexeme, right.source(PREC_UNARY))
+            }
+            Expr::Variable { name, .. } => name.lexeme.clone(),
+            Expr::Null => String::new(),
+        };
+        if self.precedence() < min_precedence {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+}
+
+// A string literal that lexes back to `s`. `\u{...}` is the only escape
+// the lexer knows, so it is used for quotes, backslashes and control
+// characters alike.
+fn quote_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        if c == '"' || c == '\\' || c.is_control() {
+            out.push_str(&format!("\\u{{{:x}}}", c as u32));
+        } else {
+            out.push(c);
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Lox source for the expression, with only the parentheses it needs.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source(PREC_ASSIGNMENT))
+    }
+}
+
+fn source_block(statements: &[Stmt], indent: usize) -> String {
+    let mut out = String::from("{\n");
+    for statement in statements {
+        out += &format!(
+            "{}{}\n",
+            "  ".repeat(indent + 1),
+            statement.source(indent + 1)
+        );
+    }
+    out + &"  ".repeat(indent) + "}"
+}
+
+impl Stmt {
+    /// Renders the statement as Lox source. Nested lines are indented one
+    /// level past `indent`; the first line is left for the caller to place.
+    fn source(&self, indent: usize) -> String {
+        match self {
+            Stmt::Block(statements) => source_block(statements, indent),
+            Stmt::Break(_) => "break;".to_string(),
+            Stmt::Continue(_) => "continue;".to_string(),
+            Stmt::DoWhile(_, body, condition) => {
+                format!("do {} while ({});", body.source(indent), condition)
+            }
+            Stmt::Export(_, declaration) => format!("export {}", declaration.source(indent)),
+            Stmt::Expression(expr) => format!("{};", expr),
+            Stmt::Function(name, params, body, return_type) => {
+                let params: Vec<&str> = params.iter().map(|p| p.lexeme.as_str()).collect();
+                let return_type = match return_type {
+                    Some(return_type) => format!(" : {}", return_type.lexeme),
+                    None => String::new(),
+                };
+                format!(
+                    "fun {}({}){} {}",
+                    name.lexeme,
+                    params.join(", "),
+                    return_type,
+                    source_block(body, indent)
+                )
+            }
+            Stmt::If(condition, then_branch, else_branch) => {
+                let mut out = format!("if ({}) {}", condition, then_branch.source(indent));
+                if let Some(else_branch) = else_branch.as_ref() {
+                    out += &format!(" else {}", else_branch.source(indent));
+                }
+                out
+            }
+            Stmt::Import(_, path, alias) => match alias {
+                Some(alias) => format!("import \"{}\" as {};", path, alias.lexeme),
+                None => format!("import \"{}\";", path),
+            },
+            Stmt::Print(exprs) => {
+                let exprs: Vec<String> = exprs.iter().map(|expr| expr.to_string()).collect();
+                format!("print {};", exprs.join(", "))
+            }
+            Stmt::Return(_, value) => match value {
+                Expr::Null => "return;".to_string(),
+                value => format!("return {};", value),
+            },
+            Stmt::Switch(subject, cases, default) => {
+                let case_indent = "  ".repeat(indent + 1);
+                let body_indent = "  ".repeat(indent + 2);
+                let mut out = format!("switch ({}) {{\n", subject);
+                let arms = cases
+                    .iter()
+                    .map(|(value, body)| (format!("case {}:", value), body))
+                    .chain(default.iter().map(|body| ("default:".to_string(), body)));
+                for (label, body) in arms {
+                    out += &format!("{}{}\n", case_indent, label);
+                    for statement in body {
+                        out += &format!("{}{}\n", body_indent, statement.source(indent + 2));
+                    }
+                }
+                out + &"  ".repeat(indent) + "}"
+            }
+            Stmt::Var(name, initializer) => match initializer {
+                Expr::Null => format!("var {};", name.lexeme),
+                initializer => format!("var {} = {};", name.lexeme, initializer),
+            },
+            // A desugared `for` keeps its increment, so it prints back as a
+            // `for` without an initializer; the initializer is in the
+            // enclosing block.
+            Stmt::While(_, condition, body, increment) => match increment {
+                Some(increment) => format!(
+                    "for (; {}; {}) {}",
+                    condition,
+                    increment,
+                    body.source(indent)
+                ),
+                None => format!("while ({}) {}", condition, body.source(indent)),
+            },
+        }
+    }
+}
+
+/// Lox source for the statement, one nested statement per line.
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source(0))
+    }
 }
 
 impl Parser {
@@ -120,27 +784,78 @@ impl Parser {
             current: 0,
             had_error: false,
             evaluate: flag,
-            error: 65,
+            silent: false,
+            errors: Vec::new(),
+            optional_final_semicolon: false,
         }
     }
 
     pub fn parse(&mut self) -> Vec<Stmt> {
+        let statements = self.parse_statements();
+        if self.had_error {
+            errors::exit(errors::EX_DATAERR);
+        }
+        statements
+    }
+
+    /// Parses the tokens as exactly one expression, with no trailing
+    /// semicolon. Errors are reported and give `None`.
+    pub fn parse_expression(&mut self) -> Option<Expr> {
+        let expr = match self.expression() {
+            Ok(expr) => expr,
+            Err(error) => {
+                self.report(&error);
+                self.had_error = true;
+                return None;
+            }
+        };
+        if !self.is_at_end() {
+            let error = ParseError::expected(self.peek()?, "Expect end of expression.");
+            self.report(&error);
+            self.had_error = true;
+            return None;
+        }
+        Some(expr)
+    }
+
+    pub fn parse_statements(&mut self) -> Vec<Stmt> {
         let mut statements: Vec<Stmt> = Vec::new();
 
         while !self.is_at_end() {
+            let start = self.current;
             if let Some(stmt) = self.declaration() {
                 statements.push(stmt);
             } else {
-                self.synchronize();
+                self.synchronize(start);
                 self.had_error = true;
             }
         }
-        if self.had_error {
-            std::process::exit(self.error);
-        }
         statements
     }
 
+    fn report(&mut self, error: &ParseError) {
+        if self.silent {
+            self.errors.push(error.clone());
+        } else {
+            errors::report_parse(&error.token, &error.message);
+        }
+    }
+
+    // Consumes the `;` ending a simple statement, or lets it be missing at
+    // the end of the file when `optional_final_semicolon` is set.
+    fn end_statement(&mut self, message: &str) -> Option<ParseError> {
+        if self.optional_final_semicolon && self.is_at_end() {
+            if !self.silent {
+                // Points just past the last token, where the `;` belongs.
+                let last = &self.tokens[self.current - 1];
+                let column = last.column + last.lexeme.chars().count();
+                errors::report_warning(last.line, column, "missing ';' at end of file.");
+            }
+            return None;
+        }
+        self.consume(TokenType::SEMICOLON, message)
+    }
+
     fn is_at_end(&self) -> bool {
         if self.current >= self.tokens.len() {
             return true;
@@ -150,21 +865,66 @@ impl Parser {
     }
 
     fn declaration(&mut self) -> Option<Stmt> {
-        if let Some(_) = self.match_token(vec![TokenType::FUN]) {
+        if self.match_token(vec![TokenType::FUN]).is_some() {
             return self.function("function");
         }
-        if let Some(_) = self.match_token(vec![TokenType::VAR]) {
+        if self.match_token(vec![TokenType::VAR]).is_some() {
             return self.var_declaration();
         }
+        if self.match_token(vec![TokenType::IMPORT]).is_some() {
+            return self.import_declaration();
+        }
+        if self.match_token(vec![TokenType::EXPORT]).is_some() {
+            return self.export_declaration();
+        }
         self.statement()
     }
 
+    fn export_declaration(&mut self) -> Option<Stmt> {
+        let keyword = self.tokens[self.current - 1].clone();
+        let declaration = if self.match_token(vec![TokenType::FUN]).is_some() {
+            self.function("function")
+        } else if self.match_token(vec![TokenType::VAR]).is_some() {
+            self.var_declaration()
+        } else {
+            let error = ParseError::expected(self.peek()?, "Expect 'fun' or 'var' after 'export'.");
+            self.had_error = true;
+            self.report(&error);
+            return None;
+        };
+        Some(Stmt::Export(keyword, Box::new(declaration?)))
+    }
+
+    fn import_declaration(&mut self) -> Option<Stmt> {
+        let keyword = self.tokens[self.current - 1].clone();
+        if let Some(error) = self.consume(TokenType::STRING, "Expect file path after 'import'.") {
+            self.report(&error);
+            return None;
+        }
+        let path = match &self.tokens[self.current - 1].literal {
+            Literal::String(path) => path.clone(),
+            _ => return None,
+        };
+        let mut alias = None;
+        if self.match_token(vec![TokenType::AS]).is_some() {
+            if let Some(error) =
+                self.consume(TokenType::IDENTIFIER, "Expect module name after 'as'.")
+            {
+                self.report(&error);
+                return None;
+            }
+            alias = Some(self.tokens[self.current - 1].clone());
+        }
+        if let Some(error) = self.consume(TokenType::SEMICOLON, "Expect ';' after import path.") {
+            self.report(&error);
+            return None;
+        }
+        Some(Stmt::Import(keyword, path, alias))
+    }
+
     fn function(&mut self, kind: &str) -> Option<Stmt> {
         if let Some(error) = self.consume(TokenType::IDENTIFIER, &format!("Expect {kind} name.")) {
-            eprintln!(
-                "Parse error at line {}: {}",
-                error.token.line, error.message
-            );
+            self.report(&error);
             return None;
         }
         let name = self.tokens[self.current - 1].clone();
@@ -172,29 +932,22 @@ impl Parser {
             TokenType::LEFT_PAREN,
             &format!("Expect '(' after {kind} name."),
         ) {
-            eprintln!(
-                "Parse error at line {}: {}",
-                error.token.line, error.message
-            );
+            self.report(&error);
             return None;
         }
         let mut parameters: Vec<Token> = Vec::new();
         if !matches!(self.peek().unwrap().token_type, TokenType::RIGHT_PAREN) {
             loop {
                 if parameters.len() >= 255 {
-                    eprintln!(
-                        "Parse error at line {}: {}",
-                        self.peek().unwrap().line,
-                        "Can't have more than 255 parameters."
-                    );
+                    self.report(&ParseError {
+                        token: self.peek().unwrap(),
+                        message: "Can't have more than 255 parameters.".to_string(),
+                    });
                     return None;
                 }
                 let param = self.peek().unwrap();
                 if let Some(error) = self.consume(TokenType::IDENTIFIER, "Expect parameter name.") {
-                    eprintln!(
-                        "Parse error at line {}: {}",
-                        error.token.line, error.message
-                    );
+                    self.report(&error);
                     return None;
                 }
                 parameters.push(param);
@@ -206,78 +959,94 @@ impl Parser {
         }
 
         if let Some(error) = self.consume(TokenType::RIGHT_PAREN, "Expect ')' after parameters.") {
-            eprintln!(
-                "Parse error at line {}: {}",
-                error.token.line, error.message
-            );
+            self.report(&error);
             return None;
         }
+        let mut return_type = None;
+        if self.match_token(vec![TokenType::COLON]).is_some() {
+            // `nil` lexes as a keyword, so it is accepted alongside names.
+            let token = self.peek()?;
+            if !matches!(token.token_type, TokenType::IDENTIFIER | TokenType::NIL) {
+                self.report(&ParseError::expected(
+                    token,
+                    "Expect return type after ':'.",
+                ));
+                return None;
+            }
+            if !TYPE_NAMES.contains(&token.lexeme.as_str()) {
+                self.report(&ParseError {
+                    message: format!("Unknown type '{}'.", token.lexeme),
+                    token,
+                });
+                return None;
+            }
+            self.advance();
+            return_type = Some(token);
+        }
         if let Some(error) = self.consume(
             TokenType::LEFT_BRACE,
             &format!("Expect '{{' before {kind} body."),
         ) {
-            eprintln!(
-                "Parse error at line {}: {}",
-                error.token.line, error.message
-            );
+            self.report(&error);
             return None;
         }
-        let body = self.block();
-        Some(Stmt::Function(name, parameters, body))
+        let body = self.block_body(true);
+        Some(Stmt::Function(name, parameters, body, return_type))
     }
 
     fn var_declaration(&mut self) -> Option<Stmt> {
         let name = match self.consume(TokenType::IDENTIFIER, "Expect variable name.") {
             Some(error) => {
-                eprintln!(
-                    "Parse error at line {}: {}",
-                    error.token.line, error.message
-                );
+                self.report(&error);
                 self.had_error = true;
-                self.error = 70;
                 return None;
             }
             None => self.tokens[self.current - 1].clone(),
         };
         let mut intializer = Expr::Null;
-        if let Some(_) = self.match_token(vec![TokenType::EQUAL]) {
+        if self.match_token(vec![TokenType::EQUAL]).is_some() {
             match self.expression() {
                 Ok(expr) => intializer = expr,
-                Err(_) => (),
+                Err(error) => {
+                    self.report(&error);
+                    return None;
+                }
             }
         }
-        if let Some(error) = self.consume(
-            TokenType::SEMICOLON,
-            "Expect ';' after variable declaration.",
-        ) {
-            eprintln!(
-                "Parse error at line {}: {}",
-                error.token.line, error.message
-            );
+        if let Some(error) = self.end_statement("Expect ';' after variable declaration.") {
+            self.report(&error);
             self.had_error = true;
-            self.error = 70;
             return None;
         }
         Some(Stmt::Var(name, intializer))
     }
 
     fn statement(&mut self) -> Option<Stmt> {
-        if let Some(_) = self.match_token(vec![TokenType::IF]) {
+        if self.match_token(vec![TokenType::IF]).is_some() {
             return self.if_statement();
         }
-        if let Some(_) = self.match_token(vec![TokenType::PRINT]) {
+        if self.match_token(vec![TokenType::PRINT]).is_some() {
             return self.print_statement();
         }
-        if let Some(_) = self.match_token(vec![TokenType::WHILE]) {
-            return self.while_statement();
+        if let Some(keyword) = self.match_token(vec![TokenType::WHILE]) {
+            return self.while_statement(keyword);
+        }
+        if let Some(keyword) = self.match_token(vec![TokenType::DO]) {
+            return self.do_while_statement(keyword);
         }
-        if let Some(_) = self.match_token(vec![TokenType::RETURN]) {
+        if self.match_token(vec![TokenType::SWITCH]).is_some() {
+            return self.switch_statement();
+        }
+        if let Some(keyword) = self.match_token(vec![TokenType::BREAK, TokenType::CONTINUE]) {
+            return self.loop_jump_statement(keyword);
+        }
+        if self.match_token(vec![TokenType::RETURN]).is_some() {
             return self.return_stmt();
         }
-        if let Some(_) = self.match_token(vec![TokenType::FOR]) {
-            return self.for_statement();
+        if let Some(keyword) = self.match_token(vec![TokenType::FOR]) {
+            return self.for_statement(keyword);
         }
-        if let Some(_) = self.match_token(vec![TokenType::LEFT_BRACE]) {
+        if self.match_token(vec![TokenType::LEFT_BRACE]).is_some() {
             return Some(Stmt::Block(self.block()));
         }
         self.expression_statement()
@@ -290,35 +1059,28 @@ impl Parser {
             match self.expression() {
                 Ok(expr) => value = Some(expr),
                 Err(error) => {
-                    eprintln!(
-                        "Parse error at line {}: {}",
-                        error.token.line, error.message
-                    );
+                    self.report(&error);
                     return None;
                 }
             }
         }
         if let Some(error) = self.consume(TokenType::SEMICOLON, "Expect ';' after return value.") {
-            eprintln!(
-                "Parse error at line {}: {}",
-                error.token.line, error.message
-            );
+            self.report(&error);
             return None;
         }
-        match value {
-            Some(v) => return Some(Stmt::Return(keyword, v)),
-            None => return None,
-        }
+        Some(Stmt::Return(keyword, value.unwrap_or(Expr::Null)))
     }
 
-    fn for_statement(&mut self) -> Option<Stmt> {
+    fn for_statement(&mut self, keyword: Token) -> Option<Stmt> {
         if let Some(error) = self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'for'.") {
-            eprintln!(
-                "Parse error at line {}: {}",
-                error.token.line, error.message
-            );
+            self.report(&error);
             return None;
         }
+        let is_range = matches!(self.peek()?.token_type, TokenType::IDENTIFIER)
+            && matches!(self.peek_at(1), Some(token) if token.token_type == TokenType::IN);
+        if is_range {
+            return self.range_for_statement(keyword);
+        }
         let initializer = match self.peek()?.token_type {
             TokenType::SEMICOLON => {
                 self.advance();
@@ -338,10 +1100,7 @@ impl Parser {
             match self.expression() {
                 Ok(expr) => Some(expr),
                 Err(error) => {
-                    eprintln!(
-                        "Parse error at line {}: {}",
-                        error.token.line, error.message
-                    );
+                    self.report(&error);
                     return None;
                 }
             }
@@ -351,10 +1110,7 @@ impl Parser {
 
         if let Some(error) = self.consume(TokenType::SEMICOLON, "Expect ';' after loop condition.")
         {
-            eprintln!(
-                "Parse error at line {}: {}",
-                error.token.line, error.message
-            );
+            self.report(&error);
             return None;
         }
 
@@ -365,10 +1121,7 @@ impl Parser {
             match self.expression() {
                 Ok(expr) => Some(expr),
                 Err(error) => {
-                    eprintln!(
-                        "Parse error at line {}: {}",
-                        error.token.line, error.message
-                    );
+                    self.report(&error);
                     return None;
                 }
             }
@@ -377,31 +1130,17 @@ impl Parser {
         };
 
         if let Some(error) = self.consume(TokenType::RIGHT_PAREN, "Expect ')' after for clauses.") {
-            eprintln!(
-                "Parse error at line {}: {}",
-                error.token.line, error.message
-            );
+            self.report(&error);
             return None;
         }
 
-        let mut body = self.statement();
-
-        if let Some(incr) = increment {
-            let mut v = Vec::new();
-            if let Some(b) = body {
-                v.push(b);
-            } else {
-                return None;
-            }
-            v.push(Stmt::Expression(incr));
-            body = Some(Stmt::Block(v));
-        }
+        let body = self.statement()?;
 
         let condition = condition.unwrap_or(Expr::Literal {
             value: Literal::Boolean(true),
         });
 
-        body = Some(Stmt::While(condition, Box::new(body?)));
+        let mut body = Some(Stmt::While(keyword, condition, Box::new(body), increment));
 
         if let Some(init) = initializer {
             body = Some(Stmt::Block(vec![init, body.unwrap()]));
@@ -410,77 +1149,263 @@ impl Parser {
         body
     }
 
-    fn while_statement(&mut self) -> Option<Stmt> {
-        match self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'while'.") {
-            Some(error) => {
-                eprintln!(
-                    "Parse error at line {}: {}",
-                    error.token.line, error.message
-                );
+    // `for (i in a..b) body` desugars to a block that declares `i` and a
+    // hidden copy of the end bound, followed by a `while` that steps `i` by
+    // one. `a..=b` includes the end bound.
+    fn range_for_statement(&mut self, keyword: Token) -> Option<Stmt> {
+        let name = self.advance()?;
+        self.advance();
+        let start = match self.expression() {
+            Ok(expr) => expr,
+            Err(error) => {
+                self.report(&error);
                 return None;
             }
-            None => (),
+        };
+        let Some(range) = self.match_token(vec![TokenType::DOT_DOT, TokenType::DOT_DOT_EQUAL])
+        else {
+            let error = ParseError::expected(self.peek()?, "Expect '..' or '..=' in range.");
+            self.had_error = true;
+            self.report(&error);
+            return None;
+        };
+        let end = match self.expression() {
+            Ok(expr) => expr,
+            Err(error) => {
+                self.report(&error);
+                return None;
+            }
+        };
+        if !may_be_integer(&start) || !may_be_integer(&end) {
+            self.had_error = true;
+            self.report(&ParseError {
+                token: range,
+                message: String::from("Range bounds must be integers."),
+            });
+            return None;
+        }
+        if let Some(error) = self.consume(TokenType::RIGHT_PAREN, "Expect ')' after for clauses.") {
+            self.report(&error);
+            return None;
+        }
+        let body = self.statement()?;
+
+        let synthetic = |token_type: TokenType, lexeme: &str| Token {
+            token_type,
+            lexeme: lexeme.to_string(),
+            line: name.line,
+            column: name.column,
+            literal: Literal::None,
+        };
+        // A real identifier, so the desugared loop still prints as source
+        // that parses back to the same tree.
+        let end_name = synthetic(TokenType::IDENTIFIER, &format!("__{}_end", name.lexeme));
+        let comparison = if range.token_type == TokenType::DOT_DOT_EQUAL {
+            synthetic(TokenType::LESS_EQUAL, "<=")
+        } else {
+            synthetic(TokenType::LESS, "<")
+        };
+        let condition = Expr::Binary {
+            left: Box::new(Expr::Variable {
+                id: next_expr_id(),
+                name: name.clone(),
+            }),
+            operator: comparison,
+            right: Box::new(Expr::Variable {
+                id: next_expr_id(),
+                name: end_name.clone(),
+            }),
+        };
+        let increment = Expr::Assign {
+            id: next_expr_id(),
+            name: name.clone(),
+            value: Box::new(Expr::Binary {
+                left: Box::new(Expr::Variable {
+                    id: next_expr_id(),
+                    name: name.clone(),
+                }),
+                operator: synthetic(TokenType::PLUS, "+"),
+                right: Box::new(Expr::Literal {
+                    value: Literal::Number(1.0),
+                }),
+            }),
+        };
+
+        Some(Stmt::Block(vec![
+            Stmt::Var(name, start),
+            Stmt::Var(end_name, end),
+            Stmt::While(keyword, condition, Box::new(body), Some(increment)),
+        ]))
+    }
+
+    fn while_statement(&mut self, keyword: Token) -> Option<Stmt> {
+        if let Some(error) = self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'while'.") {
+            self.report(&error);
+            return None;
         };
         let condition = match self.expression() {
             Ok(condition) => condition,
             Err(error) => {
-                eprintln!(
-                    "Parse error at line {}: {}",
-                    error.token.line, error.message
-                );
+                self.report(&error);
                 return None;
             }
         };
-        match self.consume(TokenType::RIGHT_PAREN, "Expect ')' after condition.") {
-            Some(error) => {
-                eprintln!(
-                    "Parse error at line {}: {}",
-                    error.token.line, error.message
-                );
+        if let Some(error) = self.consume(TokenType::RIGHT_PAREN, "Expect ')' after condition.") {
+            self.report(&error);
+            return None;
+        }
+        let body = self.statement()?;
+        Some(Stmt::While(keyword, condition, Box::new(body), None))
+    }
+
+    fn do_while_statement(&mut self, keyword: Token) -> Option<Stmt> {
+        let body = self.statement()?;
+        if let Some(error) = self.consume(TokenType::WHILE, "Expect 'while' after do body.") {
+            self.report(&error);
+            return None;
+        }
+        if let Some(error) = self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'while'.") {
+            self.report(&error);
+            return None;
+        };
+        let condition = match self.expression() {
+            Ok(condition) => condition,
+            Err(error) => {
+                self.report(&error);
                 return None;
             }
-            None => (),
+        };
+        if let Some(error) = self.consume(TokenType::RIGHT_PAREN, "Expect ')' after condition.") {
+            self.report(&error);
+            return None;
+        }
+        if let Some(error) = self.consume(TokenType::SEMICOLON, "Expect ';' after do-while.") {
+            self.report(&error);
+            return None;
+        }
+        Some(Stmt::DoWhile(keyword, Box::new(body), condition))
+    }
+
+    fn switch_statement(&mut self) -> Option<Stmt> {
+        if let Some(error) = self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'switch'.") {
+            self.report(&error);
+            return None;
         }
-        let body = match self.statement() {
-            Some(body) => body,
-            None => return None,
+        let subject = match self.expression() {
+            Ok(expr) => expr,
+            Err(error) => {
+                self.report(&error);
+                return None;
+            }
         };
-        Some(Stmt::While(condition, Box::new(body)))
+        if let Some(error) = self.consume(TokenType::RIGHT_PAREN, "Expect ')' after switch value.")
+        {
+            self.report(&error);
+            return None;
+        }
+        if let Some(error) = self.consume(TokenType::LEFT_BRACE, "Expect '{' before switch cases.")
+        {
+            self.report(&error);
+            return None;
+        }
+
+        let mut cases = Vec::new();
+        let mut default = None;
+        while let Some(label) = self.match_token(vec![TokenType::CASE, TokenType::DEFAULT]) {
+            let value = if label.token_type == TokenType::CASE {
+                match self.expression() {
+                    Ok(expr) => Some(expr),
+                    Err(error) => {
+                        self.report(&error);
+                        return None;
+                    }
+                }
+            } else {
+                None
+            };
+            if let Some(error) = self.consume(TokenType::COLON, "Expect ':' after case.") {
+                self.report(&error);
+                return None;
+            }
+            let body = self.case_body();
+            match value {
+                Some(value) => cases.push((value, body)),
+                None if default.is_none() => default = Some(body),
+                None => {
+                    let error = ParseError {
+                        token: label,
+                        message: String::from("Switch can't have more than one default."),
+                    };
+                    self.had_error = true;
+                    self.report(&error);
+                    return None;
+                }
+            }
+        }
+        if let Some(error) = self.consume(TokenType::RIGHT_BRACE, "Expect '}' after switch cases.")
+        {
+            self.report(&error);
+            return None;
+        }
+        Some(Stmt::Switch(subject, cases, default))
+    }
+
+    // Statements up to the next label or the end of the switch.
+    fn case_body(&mut self) -> Vec<Stmt> {
+        let mut statements = Vec::new();
+        while !self.is_at_end()
+            && !matches!(
+                self.peek().unwrap().token_type,
+                TokenType::CASE | TokenType::DEFAULT | TokenType::RIGHT_BRACE
+            )
+        {
+            let start = self.current;
+            if let Some(stmt) = self.declaration() {
+                statements.push(stmt);
+            } else {
+                self.synchronize(start);
+            }
+        }
+        statements
+    }
+
+    fn loop_jump_statement(&mut self, keyword: Token) -> Option<Stmt> {
+        let message = format!("Expect ';' after '{}'.", keyword.lexeme);
+        if let Some(error) = self.consume(TokenType::SEMICOLON, &message) {
+            self.report(&error);
+            return None;
+        }
+        if keyword.token_type == TokenType::BREAK {
+            Some(Stmt::Break(keyword))
+        } else {
+            Some(Stmt::Continue(keyword))
+        }
     }
 
     fn if_statement(&mut self) -> Option<Stmt> {
         if let Some(error) = self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'if'.") {
-            eprintln!(
-                "Parse error at line {}: {}",
-                error.token.line, error.message
-            );
+            self.report(&error);
             return None;
         }
         let condition = match self.expression() {
             Ok(cond) => cond,
             Err(error) => {
-                eprintln!(
-                    "Parse error at line {}: {}",
-                    error.token.line, error.message
-                );
+                self.report(&error);
                 return None;
             }
         };
         if let Some(error) = self.consume(TokenType::RIGHT_PAREN, "Expect ')' after if condition.")
         {
-            eprintln!(
-                "Parse error at line {}: {}",
-                error.token.line, error.message
-            );
+            self.report(&error);
             return None;
         }
-        let then_branch = match self.statement() {
-            Some(val) => val,
-            None => return None,
-        };
+        let then_branch = self.statement()?;
         let mut else_branch = None;
-        if let Some(_) = self.match_token(vec![TokenType::ELSE]) {
-            else_branch = self.statement();
+        // `elif (c) s` is sugar for `else if (c) s`.
+        if self.match_token(vec![TokenType::ELIF]).is_some() {
+            else_branch = Some(self.if_statement()?);
+        } else if self.match_token(vec![TokenType::ELSE]).is_some() {
+            else_branch = Some(self.statement()?);
         }
         Some(Stmt::If(
             condition,
@@ -490,73 +1415,111 @@ impl Parser {
     }
 
     fn block(&mut self) -> Vec<Stmt> {
+        self.block_body(false)
+    }
+
+    // With `tail_return`, as for a function body, a final expression with
+    // no semicolon before the `}` is returned: `fun sq(x) { x * x }`.
+    fn block_body(&mut self, tail_return: bool) -> Vec<Stmt> {
         let mut statements: Vec<Stmt> = Vec::new();
         while !self.is_at_end()
             && !matches!(self.peek().unwrap().token_type, TokenType::RIGHT_BRACE)
         {
+            if tail_return {
+                if let Some(tail) = self.tail_expression() {
+                    statements.push(tail);
+                    continue;
+                }
+            }
+            let start = self.current;
             if let Some(stmt) = self.declaration() {
                 statements.push(stmt);
             } else {
-                self.synchronize();
+                self.synchronize(start);
             }
         }
         if let Some(error) = self.consume(TokenType::RIGHT_BRACE, "Expect '}' after block.") {
-            eprintln!(
-                "Parse error at line {}: {}",
-                error.token.line, error.message
-            );
+            self.report(&error);
             self.had_error = true;
-            self.error = 65;
         }
         statements
     }
 
-    fn print_statement(&mut self) -> Option<Stmt> {
-        let value = self.expression();
-        if let Some(error) = self.consume(TokenType::SEMICOLON, "Expect ';' after value.") {
-            eprintln!(
-                "Parse error at line {}: {}",
-                error.token.line, error.message
-            );
-            self.had_error = true;
-        }
-        match value {
-            Ok(v) => Some(Stmt::Print(v)),
-            Err(error) => {
-                eprintln!(
-                    "Parse error at line {}: {}",
-                    error.token.line, error.message
-                );
-                self.had_error = true;
+    // Tries to parse an expression that runs right up to a `}` and turns it
+    // into a return. Otherwise rewinds, leaving no errors behind, so the
+    // statement can be parsed normally.
+    fn tail_expression(&mut self) -> Option<Stmt> {
+        let start = self.current;
+        let (silent, had_error, error_count) = (self.silent, self.had_error, self.errors.len());
+        self.silent = true;
+        let expr = self.expression();
+        self.silent = silent;
+        self.errors.truncate(error_count);
+        match expr {
+            Ok(expr) if self.peek()?.token_type == TokenType::RIGHT_BRACE => {
+                let keyword = Token {
+                    token_type: TokenType::RETURN,
+                    lexeme: String::from("return"),
+                    line: self.tokens[start].line,
+                    column: self.tokens[start].column,
+                    literal: Literal::None,
+                };
+                Some(Stmt::Return(keyword, expr))
+            }
+            _ => {
+                self.current = start;
+                self.had_error = had_error;
                 None
             }
         }
     }
 
-    fn expression_statement(&mut self) -> Option<Stmt> {
-        let expr = self.expression();
-        if self.evaluate {
-            if let Some(error) = self.consume(TokenType::SEMICOLON, "Expect ';' after expression.")
-            {
-                eprintln!(
-                    "Parse error at line {}: {}",
-                    error.token.line, error.message
-                );
+    // A bad value is reported before looking for the `;`, so a missing `)`
+    // gives one error rather than one for it and one for the `;`.
+    fn print_statement(&mut self) -> Option<Stmt> {
+        let values = match self.print_values() {
+            Ok(values) => values,
+            Err(error) => {
+                self.report(&error);
                 self.had_error = true;
+                return None;
             }
+        };
+        if let Some(error) = self.end_statement("Expect ';' after value.") {
+            self.report(&error);
+            self.had_error = true;
         }
+        Some(Stmt::Print(values))
+    }
 
-        match expr {
-            Ok(v) => Some(Stmt::Expression(v)),
+    fn print_values(&mut self) -> Result<Vec<Expr>, ParseError> {
+        let mut values = vec![self.expression()?];
+        while self.match_token(vec![TokenType::COMMA]).is_some() {
+            values.push(self.expression()?);
+        }
+        Ok(values)
+    }
+
+    fn expression_statement(&mut self) -> Option<Stmt> {
+        let expr = match self.expression() {
+            Ok(expr) => expr,
             Err(error) => {
-                eprintln!(
-                    "Parse error at line {}: {}",
-                    error.token.line, error.message
-                );
+                self.report(&error);
                 self.had_error = true;
-                None
+                return None;
             }
+        };
+        if self.evaluate {
+            if let Some(error) = self.end_statement("Expect ';' after expression.") {
+                self.report(&error);
+                self.had_error = true;
+            }
+        } else {
+            // Bare expressions may still end in ';', so `parse` also
+            // accepts whole programs.
+            self.match_token(vec![TokenType::SEMICOLON]);
         }
+        Some(Stmt::Expression(expr))
     }
 
     fn expression(&mut self) -> Result<Expr, ParseError> {
@@ -564,18 +1527,16 @@ impl Parser {
     }
 
     fn assignment(&mut self) -> Result<Expr, ParseError> {
-        let expr = self.or();
-        if let Some(_) = self.match_token(vec![TokenType::EQUAL]) {
+        let expr = self.coalesce();
+        if self.match_token(vec![TokenType::EQUAL]).is_some() {
             let equals = self.tokens[self.current - 1].clone();
-            let val = match self.assignment() {
-                Ok(val) => val,
-                Err(err) => return Err(err),
-            };
+            let val = self.assignment()?;
 
             match expr {
                 Ok(value) => match value {
-                    Expr::Variable { name } => {
+                    Expr::Variable { name, .. } => {
                         return Ok(Expr::Assign {
+                            id: next_expr_id(),
                             name,
                             value: Box::new(val),
                         })
@@ -593,17 +1554,28 @@ impl Parser {
         expr
     }
 
+    fn coalesce(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.or()?;
+        while self
+            .match_token(vec![TokenType::QUESTION_QUESTION])
+            .is_some()
+        {
+            let operator = self.tokens[self.current - 1].clone();
+            let right = self.or()?;
+            expr = Expr::Logical {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            }
+        }
+        Ok(expr)
+    }
+
     fn or(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = match self.and() {
-            Ok(expr) => expr,
-            Err(error) => return Err(error),
-        };
-        while let Some(_) = self.match_token(vec![TokenType::OR]) {
+        let mut expr = self.and()?;
+        while self.match_token(vec![TokenType::OR]).is_some() {
             let operator = self.tokens[self.current - 1].clone();
-            let right = match self.and() {
-                Ok(expr) => expr,
-                Err(error) => return Err(error),
-            };
+            let right = self.and()?;
             expr = Expr::Logical {
                 left: Box::new(expr),
                 operator,
@@ -614,16 +1586,10 @@ impl Parser {
     }
 
     fn and(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = match self.equality() {
-            Ok(expr) => expr,
-            Err(error) => return Err(error),
-        };
-        while let Some(_) = self.match_token(vec![TokenType::AND]) {
+        let mut expr = self.equality()?;
+        while self.match_token(vec![TokenType::AND]).is_some() {
             let operator = self.tokens[self.current - 1].clone();
-            let right = match self.equality() {
-                Ok(expr) => expr,
-                Err(error) => return Err(error),
-            };
+            let right = self.equality()?;
             expr = Expr::Logical {
                 left: Box::new(expr),
                 operator,
@@ -640,6 +1606,12 @@ impl Parser {
         None
     }
 
+    /// The token `n` places after the current one, without consuming
+    /// anything; `peek_at(0)` is the current token.
+    fn peek_at(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.current + n)
+    }
+
     fn consume(&mut self, token_type: TokenType, message: &str) -> Option<ParseError> {
         if let Some(peek) = self.peek() {
             if peek.token_type == token_type {
@@ -648,19 +1620,15 @@ impl Parser {
             }
 
             self.had_error = true;
-            self.error = 65;
-            return Some(ParseError {
-                token: peek,
-                message: message.to_string(),
-            });
+            return Some(ParseError::expected(peek, message));
         }
         self.had_error = true;
-        self.error = 65;
         Some(ParseError {
             token: Token {
                 token_type: TokenType::EOF,
                 lexeme: String::from(""),
                 line: 0,
+                column: 0,
                 literal: Literal::None,
             },
             message: format!("{} (unexpected end of input)", message),
@@ -702,6 +1670,8 @@ impl Parser {
         Ok(expr)
     }
 
+    // `1 < x < 10` parses as `(< (< 1 x) 10)`; the evaluator treats an
+    // unparenthesized comparison on the left as a chain.
     fn comparison(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.term()?;
         while let Some(op) = self.match_token(vec![
@@ -735,7 +1705,9 @@ impl Parser {
 
     fn factor(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.unary()?;
-        while let Some(op) = self.match_token(vec![TokenType::SLASH, TokenType::STAR]) {
+        while let Some(op) =
+            self.match_token(vec![TokenType::SLASH, TokenType::STAR, TokenType::DIV])
+        {
             let right = self.unary()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
@@ -758,17 +1730,25 @@ impl Parser {
     }
 
     fn call(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = self.primary();
+        let mut expr = self.primary()?;
         loop {
-            match self.match_token(vec![TokenType::LEFT_PAREN]) {
-                Some(_) => match expr {
-                    Ok(val) => expr = self.finish_call(val),
-                    Err(error) => return Err(error),
-                },
-                None => break,
+            if self.match_token(vec![TokenType::LEFT_PAREN]).is_some() {
+                expr = self.finish_call(expr)?;
+            } else if self.match_token(vec![TokenType::DOT]).is_some() {
+                if let Some(error) =
+                    self.consume(TokenType::IDENTIFIER, "Expect property name after '.'.")
+                {
+                    return Err(error);
+                }
+                expr = Expr::Get {
+                    object: Box::new(expr),
+                    name: self.tokens[self.current - 1].clone(),
+                };
+            } else {
+                break;
             }
         }
-        expr
+        Ok(expr)
     }
 
     fn finish_call(&mut self, expr: Expr) -> Result<Expr, ParseError> {
@@ -778,7 +1758,7 @@ impl Parser {
                 Ok(value) => arguments.push(value),
                 Err(error) => return Err(error),
             }
-            while let Some(_) = self.match_token(vec![TokenType::COMMA]) {
+            while self.match_token(vec![TokenType::COMMA]).is_some() {
                 match self.expression() {
                     Ok(value) => {
                         if arguments.len() >= 255 {
@@ -794,11 +1774,7 @@ impl Parser {
             }
         }
         if let Some(error) = self.consume(TokenType::RIGHT_PAREN, "Expect ')' after arguments.") {
-            eprintln!(
-                "Parse error at line {}: {}",
-                error.token.line, error.message
-            );
-            self.had_error = true;
+            return Err(error);
         }
         Ok(Expr::Call {
             callee: Box::new(expr),
@@ -830,8 +1806,10 @@ impl Parser {
                 }
                 TokenType::NUMBER => {
                     self.advance();
+                    // The lexer already parsed the value; reuse it rather
+                    // than re-parsing the lexeme.
                     Ok(Expr::Literal {
-                        value: Literal::Number(token.lexeme.parse::<f64>().unwrap()),
+                        value: token.literal.clone(),
                     })
                 }
                 TokenType::STRING => {
@@ -846,26 +1824,26 @@ impl Parser {
                     if let Some(err) =
                         self.consume(TokenType::RIGHT_PAREN, "Expect ')' after expression.")
                     {
-                        self.error = 65;
                         return Err(err);
                     }
                     Ok(Expr::Grouping {
                         expression: Box::new(expr),
                     })
                 }
+                TokenType::IF => {
+                    self.advance();
+                    self.if_expression()
+                }
                 TokenType::IDENTIFIER => {
                     self.advance();
-                    return Ok(Expr::Variable {
+                    Ok(Expr::Variable {
+                        id: next_expr_id(),
                         name: self.tokens[self.current - 1].clone(),
-                    });
+                    })
                 }
                 _ => {
-                    self.error = 65;
                     self.had_error = true;
-                    Err(ParseError {
-                        token: token.clone(),
-                        message: String::from("Expected expression."),
-                    })
+                    Err(ParseError::expected(token.clone(), "Expected expression."))
                 }
             }
         } else {
@@ -874,6 +1852,7 @@ impl Parser {
                     token_type: TokenType::EOF,
                     lexeme: String::from(""),
                     line: 0,
+                    column: 0,
                     literal: Literal::None,
                 },
                 message: String::from("Unexpected end of input."),
@@ -881,60 +1860,147 @@ impl Parser {
         }
     }
 
-    fn synchronize(&mut self) {
-        self.advance();
+    // `if (condition) then else otherwise`, after the `if`. Statements can't
+    // start with it, since there `if` begins an if statement.
+    fn if_expression(&mut self) -> Result<Expr, ParseError> {
+        let keyword = self.tokens[self.current - 1].clone();
+        if let Some(err) = self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'if'.") {
+            return Err(err);
+        }
+        let condition = self.expression()?;
+        if let Some(err) = self.consume(TokenType::RIGHT_PAREN, "Expect ')' after if condition.") {
+            return Err(err);
+        }
+        let then_branch = self.expression()?;
+        if let Some(err) = self.consume(TokenType::ELSE, "Expect 'else' in an if expression.") {
+            return Err(err);
+        }
+        let else_branch = self.expression()?;
+        Ok(Expr::If {
+            keyword,
+            condition: Box::new(condition),
+            then_branch: Box::new(then_branch),
+            else_branch: Box::new(else_branch),
+        })
+    }
+
+    // Skips to the next statement after an error in the one that began at
+    // `start`: just past a `;`, or up to (not past) a keyword that starts a
+    // statement. An error found at such a keyword leaves it for the next
+    // statement, unless the failed statement began there, so the parser
+    // always moves forward. An `else` or `elif` after the `;` belongs to
+    // the failed `if`, so it is skipped too.
+    fn synchronize(&mut self, start: usize) {
+        if self.current == start || !self.at_statement_keyword() {
+            self.advance();
+        }
         while !self.is_at_end() {
-            if self.tokens[self.current - 1].token_type == TokenType::SEMICOLON {
+            if self.tokens[self.current - 1].token_type == TokenType::SEMICOLON
+                && !self.peek().is_some_and(|token| {
+                    matches!(token.token_type, TokenType::ELSE | TokenType::ELIF)
+                })
+            {
                 return;
             }
-
-            if let Some(_) = self.match_token(vec![
-                TokenType::CLASS,
-                TokenType::FUN,
-                TokenType::VAR,
-                TokenType::FOR,
-                TokenType::IF,
-                TokenType::WHILE,
-                TokenType::PRINT,
-                TokenType::RETURN,
-            ]) {
+            if self.at_statement_keyword() {
                 return;
             }
-
             self.advance();
         }
     }
+
+    fn at_statement_keyword(&self) -> bool {
+        self.peek().is_some_and(|token| {
+            matches!(
+                token.token_type,
+                TokenType::CLASS
+                    | TokenType::FUN
+                    | TokenType::VAR
+                    | TokenType::FOR
+                    | TokenType::IF
+                    | TokenType::WHILE
+                    | TokenType::PRINT
+                    | TokenType::RETURN
+            )
+        })
+    }
 }
 
-pub fn run_parser(filename: &str) {
-    let file_contents = match fs::read_to_string(filename) {
-        Ok(contents) => contents,
-        Err(_) => {
-            writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
-            return;
-        }
-    };
+/// Parses already-scanned tokens as a program without printing anything,
+/// returning whatever statements parsed along with every error.
+pub fn parse_tokens_silently(tokens: Vec<Token>) -> (Vec<Stmt>, Vec<ParseError>) {
+    let mut parser = Parser::new(tokens, true);
+    parser.silent = true;
+    let statements = parser.parse_statements();
+    (statements, parser.errors)
+}
 
-    if file_contents.is_empty() {
-        println!("EOF  null");
-        return;
+/// Lexes and parses a whole program without printing anything or exiting.
+/// Lexer errors are returned as parse errors on the line they occurred.
+pub fn parse_source(src: &str) -> Result<Vec<Stmt>, Vec<ParseError>> {
+    let (tokens, lex_errors) = scan_tokens_silently(src);
+    let mut errors: Vec<ParseError> = lex_errors
+        .into_iter()
+        .map(|(line, column, message)| ParseError {
+            token: Token {
+                token_type: TokenType::EOF,
+                lexeme: String::new(),
+                line,
+                column,
+                literal: Literal::None,
+            },
+            message,
+        })
+        .collect();
+
+    let mut parser = Parser::new(tokens, true);
+    parser.silent = true;
+    let statements = parser.parse_statements();
+    errors.append(&mut parser.errors);
+    if errors.is_empty() && !parser.had_error {
+        Ok(statements)
+    } else {
+        Err(errors)
     }
+}
+
+/// What `parse` prints for a file that parses.
+pub enum ParseOutput {
+    /// Each top-level expression in prefix form.
+    Ast,
+    /// Every statement as an indented tree (`--tree`).
+    Tree,
+    /// Nothing; only the exit code reports success (`--quiet`).
+    Quiet,
+}
 
+pub fn run_parser(filename: &str, output: ParseOutput) {
+    let file_contents = load_source(filename);
+
+    // Every mode accepts the same input, bare expressions included; only
+    // what gets printed differs.
     let mut parser = Parser::new(return_tokens(&file_contents), false);
-    let statements = parser.parse();
-    for stmt in statements {
-        match stmt {
-            Stmt::Expression(expr) => {
-                println!("{}", expr.ast_print());
+    let statements = match output {
+        ParseOutput::Quiet => {
+            parser.parse_statements();
+            if parser.had_error {
+                errors::exit(errors::EX_DATAERR);
             }
-            Stmt::Print(expr) => {
-                println!("{}", expr.ast_print());
-            }
-            _ => (),
+            return;
+        }
+        _ => parser.parse(),
+    };
+    if let ParseOutput::Tree = output {
+        for stmt in &statements {
+            print!("{}", stmt.tree_print(0));
         }
+        return;
+    }
+    for stmt in statements {
+        println!("{}", stmt.ast_print());
     }
 
     if parser.had_error {
-        std::process::exit(65);
+        errors::exit(errors::EX_DATAERR);
     }
 }
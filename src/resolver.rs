@@ -1,220 +1,450 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::collections::HashMap;
 
 use crate::{
+    errors::{self, Diagnostic, Severity},
     evaluate::Evaluate,
-    lexer::Token,
-    parse::{Expr, Stmt},
+    lexer::{load_source, return_tokens, scan_tokens, scan_tokens_silently, Token},
+    parse::{parse_tokens_silently, Expr, Parser, Stmt},
 };
 
-pub struct Resolver {
-    evaluate: Rc<RefCell<Evaluate>>,
-    scopes: Vec<HashMap<String, bool>>,
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
 }
 
-impl Resolver {
-    pub fn new(evaluate: Rc<RefCell<Evaluate>>) -> Self {
+struct Local {
+    defined: bool,
+    // Only `var` declarations are linted; parameters and local functions
+    // start out as used.
+    used: bool,
+    line: usize,
+    column: usize,
+}
+
+pub struct Resolver<'a> {
+    evaluate: &'a mut Evaluate,
+    scopes: Vec<HashMap<String, Local>>,
+    current_function: FunctionType,
+    // How many loops and switches enclose the current statement within
+    // the current function; `break` needs one of either, `continue` a loop.
+    loop_depth: usize,
+    switch_depth: usize,
+    pub had_error: bool,
+    pub warn_unused: bool,
+    /// Warn about code after a `return`, `break` or `continue`, and about
+    /// an assignment used as a condition.
+    pub lint: bool,
+    // When set, errors and warnings are collected in `diagnostics` instead
+    // of printed.
+    pub silent: bool,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(evaluate: &'a mut Evaluate) -> Self {
         Resolver {
             evaluate,
             scopes: Vec::new(),
+            current_function: FunctionType::None,
+            loop_depth: 0,
+            switch_depth: 0,
+            had_error: false,
+            warn_unused: false,
+            lint: false,
+            silent: false,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        if self.silent {
+            self.diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                line: token.line,
+                column: token.column,
+                message: message.to_string(),
+                code: "resolve",
+            });
+        } else {
+            errors::report_parse(token, message);
+        }
+        self.had_error = true;
+    }
+
+    fn warning(&mut self, line: usize, column: usize, message: String, code: &'static str) {
+        if self.silent {
+            self.diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                line,
+                column,
+                message,
+                code,
+            });
+        } else {
+            errors::report_warning(line, column, &message);
+        }
+    }
+
+    // `if (a = b)` is usually a typo for `==`. Wrapping the assignment in
+    // its own parentheses says it is meant.
+    fn check_condition(&mut self, condition: &Expr) {
+        if let Expr::Assign { name, .. } = condition {
+            if self.lint {
+                self.warning(
+                    name.line,
+                    name.column,
+                    format!("assignment to '{}' used as a condition.", name.lexeme),
+                    "assignment-in-condition",
+                );
+            }
         }
     }
 
     fn visit_block_stmt(&mut self, stmt: &Stmt) {
         self.begin_scope();
-        match stmt {
-            Stmt::Block(statements) => (),
-            _ => (),
+        if let Stmt::Block(statements) = stmt {
+            self.resolve(statements);
         }
         self.end_scope();
     }
 
+    fn visit_export_stmt(&mut self, stmt: &Stmt) {
+        if let Stmt::Export(keyword, declaration) = stmt {
+            if !self.scopes.is_empty() || self.current_function != FunctionType::None {
+                self.error(keyword, "Can only export top-level declarations.");
+            }
+            self.resolve_single_stmt(declaration);
+        }
+    }
+
     fn visit_expression_stmt(&mut self, stmt: &Stmt) {
-        match stmt {
-            Stmt::Expression(expr) => self.resolve_single_expr(expr),
-            _ => (),
+        if let Stmt::Expression(expr) = stmt {
+            self.resolve_single_expr(expr)
         }
     }
 
     fn visit_function_stmt(&mut self, stmt: &Stmt) {
-        match stmt {
-            Stmt::Function(name, _, _) => {
-                self.declare(name);
-                self.define(name);
-            }
-            _ => (),
+        if let Stmt::Function(name, ..) = stmt {
+            self.declare(name);
+            self.define(name);
         }
-        self.resolve_function(stmt);
+        self.resolve_function(stmt, FunctionType::Function);
     }
 
     fn visit_if_stmt(&mut self, stmt: &Stmt) {
-        match stmt {
-            Stmt::If(condition, then_branch, else_branch) => {
-                self.resolve_single_expr(condition);
-                self.resolve_single_stmt(then_branch);
-                let else_branch = else_branch.clone();
-                if let Some(stmt) = *else_branch {
-                    self.resolve_single_stmt(&stmt);
-                }
+        if let Stmt::If(condition, then_branch, else_branch) = stmt {
+            self.check_condition(condition);
+            self.resolve_single_expr(condition);
+            self.resolve_single_stmt(then_branch);
+            let else_branch = else_branch.clone();
+            if let Some(stmt) = *else_branch {
+                self.resolve_single_stmt(&stmt);
             }
-            _ => (),
         }
     }
 
     fn visit_print_stmt(&mut self, stmt: &Stmt) {
-        match stmt {
-            Stmt::Print(expression) => self.resolve_single_expr(expression),
-            _ => (),
+        if let Stmt::Print(expressions) = stmt {
+            for expression in expressions {
+                self.resolve_single_expr(expression);
+            }
         }
     }
 
     fn visit_return_stmt(&mut self, stmt: &Stmt) {
-        match stmt {
-            Stmt::Return(_, value) => {
-                if !matches!(value, &Expr::Null) {
-                    self.resolve_single_expr(value);
-                }
+        if let Stmt::Return(keyword, value) = stmt {
+            if self.current_function == FunctionType::None {
+                self.error(keyword, "Can't return from top-level code.");
+            }
+            if !matches!(value, &Expr::Null) {
+                self.resolve_single_expr(value);
+            }
+        }
+    }
+
+    fn visit_break_stmt(&mut self, keyword: &Token) {
+        if self.loop_depth == 0 && self.switch_depth == 0 {
+            self.error(keyword, "Can't use 'break' outside a loop.");
+        }
+    }
+
+    fn visit_continue_stmt(&mut self, keyword: &Token) {
+        if self.loop_depth == 0 {
+            self.error(keyword, "Can't use 'continue' outside a loop.");
+        }
+    }
+
+    fn visit_switch_stmt(&mut self, stmt: &Stmt) {
+        if let Stmt::Switch(subject, cases, default) = stmt {
+            self.resolve_single_expr(subject);
+            self.switch_depth += 1;
+            for (value, body) in cases {
+                self.resolve_single_expr(value);
+                self.begin_scope();
+                self.resolve(body);
+                self.end_scope();
             }
-            _ => (),
+            if let Some(body) = default {
+                self.begin_scope();
+                self.resolve(body);
+                self.end_scope();
+            }
+            self.switch_depth -= 1;
+        }
+    }
+
+    // Imported files are resolved when they are loaded; only the alias,
+    // which the import defines in the current scope, is declared here.
+    fn visit_import_stmt(&mut self, stmt: &Stmt) {
+        if let Stmt::Import(_, _, Some(alias)) = stmt {
+            self.declare(alias);
+            self.define(alias);
         }
     }
 
     fn visit_var_stmt(&mut self, stmt: &Stmt) {
-        match stmt {
-            Stmt::Var(name, initializer) => {
-                self.declare(name);
-                if !matches!(initializer, &Expr::Null) {
-                    self.resolve_single_expr(initializer);
-                }
-                self.define(name);
+        if let Stmt::Var(name, initializer) = stmt {
+            self.declare(name);
+            if let Some(local) = self
+                .scopes
+                .last_mut()
+                .and_then(|scope| scope.get_mut(&name.lexeme))
+            {
+                local.used = false;
             }
-            _ => (),
+            if !matches!(initializer, &Expr::Null) {
+                self.resolve_single_expr(initializer);
+            }
+            self.define(name);
         }
     }
 
     fn visit_while_stmt(&mut self, stmt: &Stmt) {
-        match stmt {
-            Stmt::While(condition, body) => {
-                self.resolve_single_expr(condition);
-                self.resolve_single_stmt(body);
+        if let Stmt::While(_, condition, body, increment) = stmt {
+            self.check_condition(condition);
+            self.resolve_single_expr(condition);
+            self.loop_depth += 1;
+            self.resolve_single_stmt(body);
+            self.loop_depth -= 1;
+            if let Some(increment) = increment {
+                self.resolve_single_expr(increment);
             }
-            _ => (),
+        }
+    }
+
+    fn visit_do_while_stmt(&mut self, stmt: &Stmt) {
+        if let Stmt::DoWhile(_, body, condition) = stmt {
+            self.loop_depth += 1;
+            self.resolve_single_stmt(body);
+            self.loop_depth -= 1;
+            self.check_condition(condition);
+            self.resolve_single_expr(condition);
         }
     }
 
     fn visit_assign_expr(&mut self, expr: &Expr) {
-        match expr {
-            Expr::Assign { name, value } => {
-                self.resolve_single_expr(value);
-                self.resolve_local(expr, name);
-            }
-            _ => (),
+        if let Expr::Assign { name, value, .. } = expr {
+            self.resolve_single_expr(value);
+            self.resolve_local(expr, name);
         }
     }
 
     fn visit_binary_expr(&mut self, expr: &Expr) {
-        match expr {
-            Expr::Binary {
-                left,
-                operator,
-                right,
-            } => {
-                let _ = operator;
-                self.resolve_single_expr(left);
-                self.resolve_single_expr(right);
-            }
-            _ => (),
+        if let Expr::Binary {
+            left,
+            operator,
+            right,
+        } = expr
+        {
+            let _ = operator;
+            self.resolve_single_expr(left);
+            self.resolve_single_expr(right);
         }
     }
 
     fn visit_call_expr(&mut self, expr: &Expr) {
-        match expr {
-            Expr::Call {
-                callee,
-                paren,
-                arguments,
-            } => {
-                let _ = paren;
-                self.resolve_single_expr(&callee);
-                for args in arguments {
-                    self.resolve_single_expr(args);
-                }
+        if let Expr::Call {
+            callee,
+            paren,
+            arguments,
+        } = expr
+        {
+            let _ = paren;
+            self.resolve_single_expr(callee);
+            for args in arguments {
+                self.resolve_single_expr(args);
             }
-            _ => (),
+        }
+    }
+
+    fn visit_get_expr(&mut self, expr: &Expr) {
+        if let Expr::Get { object, .. } = expr {
+            self.resolve_single_expr(object)
         }
     }
 
     fn visit_grouping_expr(&mut self, expr: &Expr) {
-        match expr {
-            Expr::Grouping { expression } => self.resolve_single_expr(&expression),
-            _ => (),
+        if let Expr::Grouping { expression } = expr {
+            self.resolve_single_expr(expression)
+        }
+    }
+
+    fn visit_if_expr(&mut self, expr: &Expr) {
+        if let Expr::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } = expr
+        {
+            self.check_condition(condition);
+            self.resolve_single_expr(condition);
+            self.resolve_single_expr(then_branch);
+            self.resolve_single_expr(else_branch);
         }
     }
 
     fn visit_literal_expr(&mut self, _expr: &Expr) {}
 
     fn visit_logical_expr(&mut self, expr: &Expr) {
-        match expr {
-            Expr::Logical {
-                left,
-                operator,
-                right,
-            } => {
-                let _ = operator;
-                self.resolve_single_expr(left);
-                self.resolve_single_expr(right);
-            }
-            _ => (),
+        if let Expr::Logical {
+            left,
+            operator,
+            right,
+        } = expr
+        {
+            let _ = operator;
+            self.resolve_single_expr(left);
+            self.resolve_single_expr(right);
         }
     }
 
     fn visit_unary_expr(&mut self, expr: &Expr) {
-        match expr {
-            Expr::Unary { operator, right } => {
-                let _ = operator;
-                self.resolve_single_expr(right);
-            }
-            _ => (),
+        if let Expr::Unary { operator, right } = expr {
+            let _ = operator;
+            self.resolve_single_expr(right);
         }
     }
 
     fn visit_variable_expr(&mut self, expr: &Expr) {
-        match expr {
-            Expr::Variable { name } => {
-                if !self.scopes.is_empty()
-                    && self.scopes.last().unwrap().get(&name.lexeme) == Some(&false)
-                {
-                    eprintln!("Can't read local variable in its own initializer.");
-                    std::process::exit(70);
-                }
-                self.resolve_local(expr, name);
+        if let Expr::Variable { name, .. } = expr {
+            if self
+                .scopes
+                .last()
+                .and_then(|scope| scope.get(&name.lexeme))
+                .is_some_and(|local| !local.defined)
+            {
+                self.error(name, "Can't read local variable in its own initializer.");
+            }
+            if let Some(local) = self
+                .scopes
+                .iter_mut()
+                .rev()
+                .find_map(|scope| scope.get_mut(&name.lexeme))
+            {
+                local.used = true;
             }
-            _ => (),
+            self.resolve_local(expr, name);
         }
     }
 
-    fn resolve(&mut self, stmts: &Vec<Stmt>) {
+    pub fn resolve(&mut self, stmts: &[Stmt]) {
         for stmt in stmts {
             self.resolve_single_stmt(stmt);
         }
+        self.check_reachable(stmts);
     }
 
-    fn resolve_single_stmt(&mut self, stmt: &Stmt) {}
+    // Warns once per block, at the statement that makes the rest of it
+    // unreachable.
+    fn check_reachable(&mut self, stmts: &[Stmt]) {
+        if !self.lint {
+            return;
+        }
+        let jump = stmts
+            .iter()
+            .enumerate()
+            .find_map(|(index, stmt)| match stmt {
+                Stmt::Return(keyword, _) | Stmt::Break(keyword) | Stmt::Continue(keyword) => {
+                    Some((index, keyword))
+                }
+                _ => None,
+            });
+        if let Some((_, keyword)) = jump.filter(|(index, _)| index + 1 < stmts.len()) {
+            self.warning(
+                keyword.line,
+                keyword.column,
+                format!("unreachable code after '{}'.", keyword.lexeme),
+                "unreachable-code",
+            );
+        }
+    }
+
+    fn resolve_single_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(_) => self.visit_block_stmt(stmt),
+            Stmt::Break(keyword) => self.visit_break_stmt(keyword),
+            Stmt::Continue(keyword) => self.visit_continue_stmt(keyword),
+            Stmt::DoWhile(..) => self.visit_do_while_stmt(stmt),
+            Stmt::Export(..) => self.visit_export_stmt(stmt),
+            Stmt::Expression(_) => self.visit_expression_stmt(stmt),
+            Stmt::Function(..) => self.visit_function_stmt(stmt),
+            Stmt::If(..) => self.visit_if_stmt(stmt),
+            Stmt::Import(..) => self.visit_import_stmt(stmt),
+            Stmt::Print(_) => self.visit_print_stmt(stmt),
+            Stmt::Return(..) => self.visit_return_stmt(stmt),
+            Stmt::Switch(..) => self.visit_switch_stmt(stmt),
+            Stmt::Var(..) => self.visit_var_stmt(stmt),
+            Stmt::While(..) => self.visit_while_stmt(stmt),
+        }
+    }
 
-    fn resolve_single_expr(&mut self, expr: &Expr) {}
+    fn resolve_single_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign { .. } => self.visit_assign_expr(expr),
+            Expr::Binary { .. } => self.visit_binary_expr(expr),
+            Expr::Call { .. } => self.visit_call_expr(expr),
+            Expr::Get { .. } => self.visit_get_expr(expr),
+            Expr::Grouping { .. } => self.visit_grouping_expr(expr),
+            Expr::If { .. } => self.visit_if_expr(expr),
+            Expr::Literal { .. } => self.visit_literal_expr(expr),
+            Expr::Logical { .. } => self.visit_logical_expr(expr),
+            Expr::Unary { .. } => self.visit_unary_expr(expr),
+            Expr::Variable { .. } => self.visit_variable_expr(expr),
+            Expr::Null => (),
+        }
+    }
 
-    fn resolve_function(&mut self, stmt: &Stmt) {
+    fn resolve_function(&mut self, stmt: &Stmt, function_type: FunctionType) {
+        let enclosing_function = self.current_function;
+        self.current_function = function_type;
+        // A loop around the declaration doesn't make `break` valid inside
+        // the body; it would have to cross the call.
+        let enclosing_loops = std::mem::take(&mut self.loop_depth);
+        let enclosing_switches = std::mem::take(&mut self.switch_depth);
         self.begin_scope();
-        match stmt {
-            Stmt::Function(_, params, body) => {
-                for param in params {
-                    self.declare(param);
-                    self.define(param);
+        if let Stmt::Function(_, params, body, _) = stmt {
+            for param in params {
+                let duplicate = self
+                    .scopes
+                    .last()
+                    .is_some_and(|scope| scope.contains_key(&param.lexeme));
+                if duplicate {
+                    self.error(
+                        param,
+                        &format!("Duplicate parameter name '{}'.", param.lexeme),
+                    );
                 }
-                self.resolve(body);
+                self.declare(param);
+                self.define(param);
             }
-            _ => (),
+            self.resolve(body);
         }
         self.end_scope();
+        self.current_function = enclosing_function;
+        self.loop_depth = enclosing_loops;
+        self.switch_depth = enclosing_switches;
     }
 
     fn begin_scope(&mut self) {
@@ -222,28 +452,221 @@ impl Resolver {
     }
 
     fn end_scope(&mut self) {
-        self.scopes.pop();
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        if !self.warn_unused {
+            return;
+        }
+        let mut unused: Vec<(&String, &Local)> =
+            scope.iter().filter(|(_, local)| !local.used).collect();
+        unused.sort_by_key(|(_, local)| local.line);
+        for (name, local) in unused {
+            let message = format!("unused variable '{}'.", name);
+            self.warning(local.line, local.column, message, "unused-variable");
+        }
     }
 
     fn declare(&mut self, name: &Token) {
         if let Some(scope) = self.scopes.last_mut() {
-            scope.insert(name.lexeme.clone(), false);
+            scope.insert(
+                name.lexeme.clone(),
+                Local {
+                    defined: false,
+                    used: true,
+                    line: name.line,
+                    column: name.column,
+                },
+            );
         }
     }
 
     fn define(&mut self, name: &Token) {
-        if let Some(scope) = self.scopes.last_mut() {
-            scope.insert(name.lexeme.clone(), true);
+        if let Some(local) = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.get_mut(&name.lexeme))
+        {
+            local.defined = true;
         }
     }
 
     fn resolve_local(&mut self, expr: &Expr, name: &Token) {
-        for i in self.scopes.len() - 1..0 {
-            if self.scopes.get(i).unwrap().contains_key(&name.lexeme) {
-                self.evaluate
-                    .borrow_mut()
-                    .resolve(expr, self.scopes.len() - 1);
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if scope.contains_key(&name.lexeme) {
+                self.evaluate.resolve(expr, depth);
+                return;
+            }
+        }
+    }
+}
+
+/// Lexes, parses and resolves `src` without running it or printing
+/// anything, returning every error plus the lint warnings, ordered by
+/// position.
+pub fn analyze(src: &str) -> Vec<Diagnostic> {
+    let (tokens, lex_errors) = scan_tokens_silently(src);
+    let mut diagnostics: Vec<Diagnostic> = lex_errors
+        .into_iter()
+        .map(|(line, column, message)| Diagnostic {
+            severity: Severity::Error,
+            line,
+            column,
+            message,
+            code: "lex",
+        })
+        .collect();
+
+    let (statements, parse_errors) = parse_tokens_silently(tokens);
+    diagnostics.extend(parse_errors.iter().map(|error| Diagnostic {
+        severity: Severity::Error,
+        line: error.line(),
+        column: error.column(),
+        message: error.message().to_string(),
+        code: "parse",
+    }));
+
+    let mut evaluate = Evaluate::new();
+    let mut resolver = Resolver::new(&mut evaluate);
+    resolver.silent = true;
+    resolver.warn_unused = true;
+    resolver.lint = true;
+    resolver.resolve(&statements);
+    diagnostics.append(&mut resolver.diagnostics);
+
+    diagnostics.sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+    diagnostics
+}
+
+pub fn run_check(filename: &str, warn_unused: bool) {
+    let file_contents = load_source(filename);
+
+    let (tokens, lex_error) = scan_tokens(&file_contents);
+    let mut parser = Parser::new(tokens, true);
+    let statements = parser.parse_statements();
+    let mut evaluate = Evaluate::new();
+    let mut resolver = Resolver::new(&mut evaluate);
+    resolver.warn_unused = warn_unused;
+    resolver.lint = true;
+    resolver.resolve(&statements);
+
+    if lex_error || parser.had_error || resolver.had_error {
+        errors::exit(errors::EX_DATAERR);
+    }
+}
+
+pub fn run_resolve_dump(filename: &str) {
+    let file_contents = load_source(filename);
+
+    let mut parser = Parser::new(return_tokens(&file_contents), true);
+    let statements = parser.parse();
+    let mut evaluate = Evaluate::new();
+    let mut resolver = Resolver::new(&mut evaluate);
+    resolver.resolve(&statements);
+    if resolver.had_error {
+        errors::exit(errors::EX_DATAERR);
+    }
+
+    for stmt in &statements {
+        dump_stmt(stmt, evaluate.locals());
+    }
+}
+
+fn dump_stmt(stmt: &Stmt, locals: &HashMap<usize, usize>) {
+    match stmt {
+        Stmt::Block(statements) => {
+            for stmt in statements {
+                dump_stmt(stmt, locals);
+            }
+        }
+        Stmt::Export(_, declaration) => dump_stmt(declaration, locals),
+        Stmt::Expression(expr) => dump_expr(expr, locals),
+        Stmt::Function(_, _, body, _) => {
+            for stmt in body {
+                dump_stmt(stmt, locals);
+            }
+        }
+        Stmt::If(condition, then_branch, else_branch) => {
+            dump_expr(condition, locals);
+            dump_stmt(then_branch, locals);
+            if let Some(stmt) = else_branch.as_ref() {
+                dump_stmt(stmt, locals);
+            }
+        }
+        Stmt::Import(..) => (),
+        Stmt::Print(exprs) => {
+            for expr in exprs {
+                dump_expr(expr, locals);
+            }
+        }
+        Stmt::Return(_, value) => dump_expr(value, locals),
+        Stmt::Switch(subject, cases, default) => {
+            dump_expr(subject, locals);
+            for (value, body) in cases {
+                dump_expr(value, locals);
+                for stmt in body {
+                    dump_stmt(stmt, locals);
+                }
+            }
+            for stmt in default.iter().flatten() {
+                dump_stmt(stmt, locals);
+            }
+        }
+        Stmt::Var(_, initializer) => dump_expr(initializer, locals),
+        Stmt::While(_, condition, body, increment) => {
+            dump_expr(condition, locals);
+            dump_stmt(body, locals);
+            if let Some(increment) = increment {
+                dump_expr(increment, locals);
             }
         }
+        Stmt::DoWhile(_, body, condition) => {
+            dump_stmt(body, locals);
+            dump_expr(condition, locals);
+        }
+        Stmt::Break(_) | Stmt::Continue(_) => (),
+    }
+}
+
+fn dump_expr(expr: &Expr, locals: &HashMap<usize, usize>) {
+    match expr {
+        Expr::Assign { id, name, value } => {
+            dump_reference(*id, name, locals);
+            dump_expr(value, locals);
+        }
+        Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+            dump_expr(left, locals);
+            dump_expr(right, locals);
+        }
+        Expr::Call {
+            callee, arguments, ..
+        } => {
+            dump_expr(callee, locals);
+            for arg in arguments {
+                dump_expr(arg, locals);
+            }
+        }
+        Expr::Get { object, .. } => dump_expr(object, locals),
+        Expr::Grouping { expression } => dump_expr(expression, locals),
+        Expr::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            dump_expr(condition, locals);
+            dump_expr(then_branch, locals);
+            dump_expr(else_branch, locals);
+        }
+        Expr::Unary { right, .. } => dump_expr(right, locals),
+        Expr::Variable { id, name } => dump_reference(*id, name, locals),
+        Expr::Literal { .. } | Expr::Null => (),
+    }
+}
+
+fn dump_reference(id: usize, name: &Token, locals: &HashMap<usize, usize>) {
+    match locals.get(&id) {
+        Some(depth) => println!("line {}: '{}' -> depth {}", name.line, name.lexeme, depth),
+        None => println!("line {}: '{}' -> global", name.line, name.lexeme),
     }
 }
@@ -0,0 +1,139 @@
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::errors;
+use crate::evaluate::{Evaluate, RuntimeError, Value};
+use crate::lexer::{self, load_source, return_tokens};
+use crate::parse::Parser;
+use crate::resolver::Resolver;
+
+/// Switches for the `run` command; all off by default.
+#[derive(Clone, Default)]
+pub struct RunOptions {
+    /// `--trace`: log each statement to stderr before it runs.
+    pub trace: bool,
+    /// `--lenient`: read undefined variables as nil with a warning.
+    pub lenient: bool,
+    /// `--sandbox`: for untrusted scripts. `clock`, `benchmark`,
+    /// `readFile` and `writeFile` are not defined, `random` and `randomInt`
+    /// start from a fixed seed rather than the time, and `import` is
+    /// refused, so a script can neither touch files nor observe the clock.
+    pub sandbox: bool,
+    /// `--max-iterations=N`: stop any single loop whose body runs more
+    /// than N times. Unlimited when None.
+    pub max_iterations: Option<usize>,
+    /// `--strict`: a missing `;` at the end of the file is an error, as
+    /// anywhere else, rather than a warning.
+    pub strict: bool,
+    /// Everything after `--` on the command line, given to the script as
+    /// the global `args` list of strings.
+    pub args: Vec<String>,
+}
+
+/// Takes one script through parsing, resolution and evaluation. In
+/// expression mode (the `evaluate` command) statements may be bare
+/// expressions and each expression statement's value is printed.
+pub struct Runner {
+    evaluate: Evaluate,
+    expression_mode: bool,
+    strict: bool,
+}
+
+impl Runner {
+    pub fn new(expression_mode: bool, options: RunOptions) -> Self {
+        let mut evaluate = Evaluate::new();
+        evaluate.trace = options.trace;
+        evaluate.lenient = options.lenient;
+        evaluate.sandbox = options.sandbox;
+        evaluate.max_iterations = options.max_iterations;
+        evaluate.define_globals();
+        let args = options.args.into_iter().map(Value::String).collect();
+        evaluate.define_global("args", Value::List(Rc::new(RefCell::new(args))));
+        Runner {
+            evaluate,
+            expression_mode,
+            strict: options.strict,
+        }
+    }
+
+    // Exits like the reference implementation: 65 for a static error, 70
+    // for a runtime error.
+    pub fn run(&mut self, filename: &str, source: &str) {
+        let mut parser = Parser::new(return_tokens(source), !self.expression_mode);
+        parser.optional_final_semicolon = !self.expression_mode && !self.strict;
+        self.evaluate.enter_script(Path::new(filename));
+        let statements = parser.parse();
+        let mut resolver = Resolver::new(&mut self.evaluate);
+        resolver.resolve(&statements);
+        if resolver.had_error {
+            errors::exit(errors::EX_DATAERR);
+        }
+        for stmt in statements {
+            if let Err(RuntimeError::Error {
+                message,
+                line,
+                token,
+            }) = self.evaluate.execute(stmt, self.expression_mode)
+            {
+                self.evaluate.report_runtime(line, token.column, &message);
+                errors::exit(errors::EX_SOFTWARE);
+            }
+        }
+        if parser.had_error && !self.expression_mode {
+            errors::exit(errors::EX_DATAERR);
+        }
+    }
+}
+
+pub fn run_file(filename: &str, expression_mode: bool, options: RunOptions) {
+    let file_contents = load_source(filename);
+    Runner::new(expression_mode, options).run(filename, &file_contents);
+}
+
+// The `eval-expr` command: the file holds a single expression, whose value
+// is printed.
+pub fn run_expression(filename: &str) {
+    let file_contents = load_source(filename);
+    let mut parser = Parser::new(return_tokens(&file_contents), false);
+    let Some(expr) = parser.parse_expression() else {
+        errors::exit(errors::EX_DATAERR);
+    };
+    let mut evaluate = Evaluate::new();
+    evaluate.define_globals();
+    match evaluate.eval_expr(&expr) {
+        Ok(value) => println!("{}", value),
+        Err(RuntimeError::Error {
+            message,
+            line,
+            token,
+        }) => {
+            evaluate.report_runtime(line, token.column, &message);
+            errors::exit(errors::EX_SOFTWARE);
+        }
+        Err(_) => (),
+    }
+}
+
+// The `debug` command: prints the tokens, then the statement tree, then
+// runs the program, with a header before each section.
+pub fn run_debug(filename: &str) {
+    let file_contents = load_source(filename);
+
+    println!("== tokens ==");
+    for token in return_tokens(&file_contents) {
+        println!("{}", lexer::to_string(token));
+    }
+
+    println!("== ast ==");
+    let mut parser = Parser::new(return_tokens(&file_contents), true);
+    for stmt in &parser.parse() {
+        print!("{}", stmt.tree_print(0));
+    }
+    if parser.had_error {
+        errors::exit(errors::EX_DATAERR);
+    }
+
+    println!("== output ==");
+    Runner::new(false, RunOptions::default()).run(filename, &file_contents);
+}
@@ -0,0 +1,58 @@
+// `resolver::analyze` reports everything wrong with a file at once, from
+// every front-end stage, without running it.
+
+use interpreter::errors::{Diagnostic, Severity};
+use interpreter::resolver::analyze;
+
+fn summary(diagnostics: &[Diagnostic]) -> Vec<(Severity, usize, usize, &str)> {
+    diagnostics
+        .iter()
+        .map(|d| (d.severity, d.line, d.column, d.code))
+        .collect()
+}
+
+#[test]
+fn reports_every_stage_in_order() {
+    let source = "var a = 1;\n\
+                  print @;\n\
+                  fun f() {\n  \
+                    var unused = 1;\n  \
+                    return 1;\n  \
+                    print 2;\n\
+                  }\n\
+                  if (a = 2) print a;\n\
+                  return;\n";
+    let diagnostics = analyze(source);
+    assert_eq!(
+        summary(&diagnostics),
+        vec![
+            (Severity::Error, 2, 7, "lex"),
+            (Severity::Error, 2, 8, "parse"),
+            (Severity::Warning, 4, 7, "unused-variable"),
+            (Severity::Warning, 5, 3, "unreachable-code"),
+            (Severity::Warning, 8, 5, "assignment-in-condition"),
+            (Severity::Error, 9, 1, "resolve"),
+        ]
+    );
+    assert_eq!(diagnostics[0].message, "Unexpected character: @");
+    assert_eq!(diagnostics[3].message, "unreachable code after 'return'.");
+    assert_eq!(
+        diagnostics[4].message,
+        "assignment to 'a' used as a condition."
+    );
+}
+
+#[test]
+fn parenthesized_assignment_and_last_statement_jump_are_fine() {
+    let source = "var a;\n\
+                  while ((a = nil) != nil) {}\n\
+                  fun f() { print a; return a; }\n\
+                  for (var i = 0; i < 3; i = i + 1) { if (i == 1) break; }\n";
+    assert_eq!(analyze(source), vec![]);
+}
+
+#[test]
+fn columns_count_characters_not_bytes() {
+    let diagnostics = analyze("print \"é\" + é + @;\n");
+    assert_eq!(summary(&diagnostics)[0], (Severity::Error, 1, 17, "lex"));
+}
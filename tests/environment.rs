@@ -0,0 +1,76 @@
+// A resolved depth that doesn't match the scope chain is an error, never
+// a panic or a silent read or write of some other variable.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use interpreter::environment::Environment;
+use interpreter::evaluate::{RuntimeError, Value};
+use interpreter::lexer::{Literal, Token, TokenType};
+
+fn name(lexeme: &str) -> Token {
+    Token {
+        token_type: TokenType::IDENTIFIER,
+        lexeme: lexeme.to_string(),
+        line: 1,
+        column: 1,
+        literal: Literal::None,
+    }
+}
+
+fn message<T>(result: Result<T, RuntimeError>) -> String {
+    match result {
+        Err(RuntimeError::Error { message, .. }) => message,
+        _ => panic!("expected an error"),
+    }
+}
+
+fn chain() -> Rc<RefCell<Environment>> {
+    let outer = Rc::new(RefCell::new(Environment::new()));
+    outer
+        .borrow_mut()
+        .define("a".to_string(), Value::Number(1.0));
+    Rc::new(RefCell::new(Environment::from_enclosing(outer)))
+}
+
+#[test]
+fn resolved_depth_finds_the_variable() {
+    let inner = chain();
+    assert!(matches!(
+        Environment::get_at(&inner, 1, &name("a")),
+        Ok(Value::Number(n)) if n == 1.0
+    ));
+    assert!(Environment::assign_at(&inner, 1, &name("a"), Value::Number(2.0)).is_ok());
+    assert!(matches!(
+        Environment::get_at(&inner, 1, &name("a")),
+        Ok(Value::Number(n)) if n == 2.0
+    ));
+}
+
+#[test]
+fn depth_past_the_chain_is_an_error() {
+    let inner = chain();
+    assert_eq!(
+        message(Environment::get_at(&inner, 5, &name("a"))),
+        "Undefined variable 'a'."
+    );
+    assert_eq!(
+        message(Environment::assign_at(&inner, 5, &name("a"), Value::Nil)),
+        "Undefined variable 'a'."
+    );
+}
+
+#[test]
+fn missing_name_at_the_depth_is_an_error() {
+    let inner = chain();
+    assert_eq!(
+        message(Environment::get_at(&inner, 0, &name("a"))),
+        "Undefined variable 'a'."
+    );
+    assert_eq!(
+        message(Environment::assign_at(&inner, 0, &name("a"), Value::Nil)),
+        "Undefined variable 'a'."
+    );
+    // The failed assignment didn't define `a` in the inner scope.
+    assert!(inner.borrow().lookup("a").is_none());
+}
@@ -0,0 +1,191 @@
+// Runs each script in tests/fixtures through the interpreter binary and
+// checks it against the expectations written in its comments:
+//
+//   // expect: <line>     the next line of stdout, in order
+//   // stderr: <text>     text that must appear somewhere in stderr
+//   // errors: <count>    how many lines of stderr report an error, to
+//                         catch cascades; unchecked when absent
+//   // exit: <code>       the exit code; 0 when absent
+//   // command: <args>    the arguments, with {} for the script's path and
+//                         {tmp} for a scratch file only this fixture uses;
+//                         "run {}" when absent
+
+use std::path::Path;
+use std::process::Command;
+
+fn run_fixture(name: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    let source = std::fs::read_to_string(&path).expect("fixture should be readable");
+
+    let mut expected_stdout = String::new();
+    let mut expected_stderr = Vec::new();
+    let mut expected_errors = None;
+    let mut expected_exit = 0;
+    let mut command = String::from("run {}");
+    for line in source.lines() {
+        let Some((_, comment)) = line.split_once("// ") else {
+            continue;
+        };
+        if let Some(text) = comment.strip_prefix("expect: ") {
+            expected_stdout.push_str(text);
+            expected_stdout.push('\n');
+        } else if let Some(text) = comment.strip_prefix("stderr: ") {
+            expected_stderr.push(text.to_string());
+        } else if let Some(count) = comment.strip_prefix("errors: ") {
+            expected_errors = Some(
+                count
+                    .trim()
+                    .parse()
+                    .expect("error count should be a number"),
+            );
+        } else if let Some(code) = comment.strip_prefix("exit: ") {
+            expected_exit = code.trim().parse().expect("exit code should be a number");
+        } else if let Some(args) = comment.strip_prefix("command: ") {
+            command = args.to_string();
+        }
+    }
+
+    let scratch = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join(name)
+        .with_extension("txt");
+    let _ = std::fs::remove_file(&scratch);
+    let path = path.to_str().expect("fixture path should be UTF-8");
+    let scratch = scratch.to_str().expect("scratch path should be UTF-8");
+    let output = Command::new(env!("CARGO_BIN_EXE_codecrafters-interpreter"))
+        .arg("--no-color")
+        .args(
+            command
+                .split_whitespace()
+                .map(|arg| arg.replace("{tmp}", scratch).replace("{}", path)),
+        )
+        .output()
+        .expect("interpreter should start");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(stdout, expected_stdout, "stdout of {}", name);
+    for text in &expected_stderr {
+        assert!(
+            stderr.contains(text.as_str()),
+            "stderr of {} should contain {:?}, got:\n{}",
+            name,
+            text,
+            stderr
+        );
+    }
+    if let Some(count) = expected_errors {
+        let errors = stderr.lines().filter(|line| line.contains("Error")).count();
+        assert_eq!(
+            errors, count,
+            "error lines in stderr of {}:\n{}",
+            name, stderr
+        );
+    }
+    assert_eq!(
+        output.status.code(),
+        Some(expected_exit),
+        "exit code of {}; stderr:\n{}",
+        name,
+        stderr
+    );
+}
+
+macro_rules! lox_test {
+    ($name:ident) => {
+        #[test]
+        fn $name() {
+            run_fixture(concat!(stringify!($name), ".lox"));
+        }
+    };
+}
+
+lox_test!(split_join_round_trip);
+lox_test!(join_stringifies_elements);
+lox_test!(random_int_ranges);
+lox_test!(random_int_reversed_range);
+lox_test!(print_multiple_values);
+lox_test!(nil_coalescing);
+lox_test!(chained_comparison);
+lox_test!(chained_comparison_grouped);
+lox_test!(for_range);
+lox_test!(unicode_escape);
+lox_test!(unicode_escape_invalid);
+lox_test!(do_while);
+lox_test!(switch_statement);
+lox_test!(import_alias_in_block);
+lox_test!(import_alias_in_function);
+lox_test!(elif_same_tree_as_else_if);
+lox_test!(elif_behaves_like_else_if);
+lox_test!(div_operator);
+lox_test!(div_by_zero);
+lox_test!(break_at_top_level);
+lox_test!(break_in_function_outside_loop);
+lox_test!(exit_code_syntax_error);
+lox_test!(exit_code_runtime_error);
+lox_test!(duplicate_parameter);
+lox_test!(implicit_final_return);
+lox_test!(json_errors_columns);
+lox_test!(sort_comparator_error);
+lox_test!(sort_comparator_nan);
+lox_test!(sort_comparator_not_a_total_order);
+lox_test!(if_expression);
+lox_test!(if_expression_without_else);
+lox_test!(max_iterations_infinite_for);
+lox_test!(max_iterations_infinite_while);
+lox_test!(max_iterations_do_while);
+lox_test!(copy_nested_list);
+lox_test!(missing_final_semicolon);
+lox_test!(missing_final_semicolon_strict);
+lox_test!(script_args);
+lox_test!(script_args_empty);
+lox_test!(read_write_file);
+lox_test!(read_file_missing);
+lox_test!(read_file_sandboxed);
+lox_test!(short_circuit);
+lox_test!(equality);
+lox_test!(parse_quiet_expression);
+lox_test!(parse_quiet_error);
+lox_test!(parse_tree_expression);
+lox_test!(for_range_fractional);
+lox_test!(missing_paren_one_error);
+lox_test!(elif_broken_branch);
+lox_test!(else_broken_branch);
+lox_test!(resolved_locals);
+lox_test!(integer_arithmetic);
+lox_test!(eval_expr_sum);
+lox_test!(eval_expr_concat);
+lox_test!(eval_expr_runtime_error);
+lox_test!(eval_expr_statement);
+lox_test!(to_fixed);
+lox_test!(sort_ordering);
+lox_test!(parse_statements);
+lox_test!(empty_tokenize);
+lox_test!(empty_parse);
+lox_test!(empty_evaluate);
+lox_test!(empty_run);
+lox_test!(non_finite_numbers);
+lox_test!(negative_zero);
+lox_test!(multibyte_chars);
+lox_test!(multibyte_error_column);
+lox_test!(runtime_error_in_if_branch);
+lox_test!(shadowing);
+lox_test!(assign_closed_over);
+lox_test!(nil_operands);
+lox_test!(nil_add);
+lox_test!(nil_concat);
+lox_test!(nil_negate);
+lox_test!(nil_compare);
+lox_test!(nil_multiply);
+lox_test!(nil_call);
+lox_test!(trailing_comment_no_newline);
+lox_test!(trailing_comment_lex_error);
+lox_test!(chained_assignment);
+lox_test!(source_unreadable);
+lox_test!(source_invalid_utf8);
+lox_test!(json_errors_unreadable);
+lox_test!(json_errors_invalid_utf8);
+lox_test!(math_overflow);
+lox_test!(math_log_zero);
+lox_test!(math_domain_error);
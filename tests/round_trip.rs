@@ -0,0 +1,62 @@
+// Printing a program with `Display` and parsing the result again must give
+// the same tree, and printing that tree must give the same source.
+
+use interpreter::lexer::scan_tokens;
+use interpreter::parse::{parse_tokens_silently, Stmt};
+
+fn parse(source: &str) -> Vec<Stmt> {
+    let (tokens, had_error) = scan_tokens(source);
+    assert!(!had_error, "lex errors in {:?}", source);
+    let (statements, errors) = parse_tokens_silently(tokens);
+    assert!(errors.is_empty(), "parse errors in {:?}", source);
+    statements
+}
+
+fn render(statements: &[Stmt]) -> String {
+    statements
+        .iter()
+        .map(|stmt| format!("{}\n", stmt))
+        .collect()
+}
+
+fn tree(statements: &[Stmt]) -> Vec<String> {
+    statements.iter().map(Stmt::ast_print).collect()
+}
+
+fn assert_round_trips(source: &str) {
+    let original = parse(source);
+    let printed = render(&original);
+    let reparsed = parse(&printed);
+    assert_eq!(tree(&original), tree(&reparsed), "printed as:\n{}", printed);
+    assert_eq!(printed, render(&reparsed));
+}
+
+#[test]
+fn precedence_keeps_needed_parentheses() {
+    assert_round_trips("print (1 + 2) * 3 - -(4 - 5);");
+    assert_round_trips("var a = (1 < 2) == (3 >= 4) and !(true or false);");
+    assert_round_trips("print nil ?? (1 ?? 2);");
+}
+
+#[test]
+fn statements_round_trip() {
+    assert_round_trips(
+        "fun f(a, b) { if (a < b) { return a; } else { return b; } }\n\
+         var x = f(1, 2);\n\
+         while (x < 10) x = x + 1;\n\
+         for (var i = 0; i < 3; i = i + 1) print i;",
+    );
+}
+
+#[test]
+fn strings_with_quotes_and_backslashes_round_trip() {
+    assert_round_trips(r#"print "say \u{22}hi\u{22}";"#);
+    assert_round_trips(r#"print "back\slash" + "\u{5c}u{41}";"#);
+    assert_round_trips("print \"two\nlines\";");
+}
+
+#[test]
+fn escaped_string_prints_as_escape() {
+    let statements = parse(r#"print "a\u{22}b";"#);
+    assert_eq!(render(&statements), "print \"a\\u{22}b\";\n");
+}